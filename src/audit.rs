@@ -0,0 +1,401 @@
+//! Security audit of PATH.
+//!
+//! Anyone who can write to a directory that is searched before the system
+//! directories can shadow `sudo` or `ssh` with their own program. `audit`
+//! reports such directories, world-writable files and entries that depend
+//! on the current directory, each with a hint on how to fix it.
+
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::doctor::Severity;
+use crate::path_entry::{EntryKind, PathEntry};
+use crate::search;
+
+/// Directories only root should be able to write to.
+const SYSTEM_DIRS: &[&str] = &[
+    "/usr/local/sbin",
+    "/usr/local/bin",
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+];
+
+/// Commands worth hijacking: they are run all the time, and some of them
+/// are handed passwords or keys.
+const CORE_UTILITIES: &[&str] = &[
+    "bash", "cat", "chmod", "chown", "cp", "curl", "env", "find", "git", "gpg", "grep", "id",
+    "kill", "ln", "login", "ls", "mount", "mv", "passwd", "ps", "rm", "scp", "sftp", "sh", "ssh",
+    "su", "sudo", "wget", "which", "zsh",
+];
+
+/// Who besides root can write to a directory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Writer {
+    /// The user running the audit owns it
+    You,
+    /// Another user owns it
+    Owner(u32),
+    /// It is group-writable
+    Group(u32),
+    /// It is world-writable
+    Others,
+}
+
+impl fmt::Display for Writer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Writer::You => write!(f, "you"),
+            Writer::Owner(uid) => write!(f, "its owner (uid {})", uid),
+            Writer::Group(gid) => write!(f, "group {}", gid),
+            Writer::Others => write!(f, "everyone"),
+        }
+    }
+}
+
+/// Who besides root can write to a file with the given mode and owner.
+/// The owner can always `chmod` it writable, whatever its mode.
+fn writer(mode: u32, uid: u32, gid: u32, current_uid: u32) -> Option<Writer> {
+    if mode & 0o002 != 0 {
+        Some(Writer::Others)
+    } else if mode & 0o020 != 0 && gid != 0 {
+        Some(Writer::Group(gid))
+    } else if uid == 0 {
+        None
+    } else if uid == current_uid {
+        Some(Writer::You)
+    } else {
+        Some(Writer::Owner(uid))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    /// Empty entry, which runs commands from the current directory
+    Empty,
+    /// Relative entry, which runs commands from wherever the shell is
+    Relative,
+    /// Directory writable by someone other than root, searched before the
+    /// system directory `system`
+    WritableBeforeSystem {
+        writer: Writer,
+        system: String,
+    },
+    /// Group- or world-writable directory after the system directories
+    WritableDir(Writer),
+    WorldWritableFile(PathBuf),
+    /// A core utility that resolves to a writable directory, shadowing the
+    /// copy in `shadowed`
+    ShadowsCoreUtility {
+        writer: Writer,
+        shadowed: PathBuf,
+    },
+}
+
+/// A problem with the entry at `index` of the path list, or with the file
+/// `path` in it.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub index: usize,
+    /// The entry as written, or the file the finding is about
+    pub path: String,
+    pub issue: Issue,
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        match &self.issue {
+            Issue::Empty
+            | Issue::Relative
+            | Issue::WorldWritableFile(_)
+            | Issue::ShadowsCoreUtility { .. } => Severity::Error,
+            Issue::WritableBeforeSystem { writer, .. } => match writer {
+                Writer::You | Writer::Owner(_) => Severity::Warning,
+                Writer::Group(_) | Writer::Others => Severity::Error,
+            },
+            Issue::WritableDir(Writer::Others) => Severity::Warning,
+            Issue::WritableDir(_) => Severity::Note,
+        }
+    }
+
+    /// How to fix the problem.
+    pub fn hint(&self) -> String {
+        let path = &self.path;
+        match &self.issue {
+            Issue::Empty => {
+                "remove the empty entry (look for '::' or a leading or trailing ':')".to_string()
+            }
+            Issue::Relative => "use an absolute path, or remove the entry".to_string(),
+            Issue::WritableBeforeSystem { writer, system } => match writer {
+                Writer::You | Writer::Owner(_) => format!("move {} after {} in PATH", path, system),
+                Writer::Group(_) => format!("chmod g-w {}", path),
+                Writer::Others => format!("chmod o-w {}", path),
+            },
+            Issue::WritableDir(Writer::Group(_)) => format!("chmod g-w {}", path),
+            Issue::WritableDir(_) | Issue::WorldWritableFile(_) => format!("chmod o-w {}", path),
+            Issue::ShadowsCoreUtility { .. } => format!(
+                "check where {} came from, and remove it if you did not put it there",
+                path
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.issue {
+            Issue::Empty => write!(f, "empty entry runs commands from the current directory"),
+            Issue::Relative => write!(f, "relative entry runs commands from the current directory"),
+            Issue::WritableBeforeSystem { writer, system } => {
+                write!(f, "writable by {}, searched before {}", writer, system)
+            }
+            Issue::WritableDir(writer) => write!(f, "writable by {}", writer),
+            Issue::WorldWritableFile(_) => write!(f, "world-writable"),
+            Issue::ShadowsCoreUtility { writer, shadowed } => write!(
+                f,
+                "shadows {} from a directory writable by {}",
+                shadowed.display(),
+                writer
+            ),
+        }
+    }
+}
+
+/// Audit a path list, returning findings in list order. `current_uid` is
+/// the user the audit is for.
+pub fn audit(entries: &[PathEntry], current_uid: u32) -> Vec<Finding> {
+    let writers: Vec<Option<Writer>> = entries
+        .iter()
+        .map(|entry| {
+            let meta = fs::metadata(&entry.dir).ok()?;
+            writer(meta.mode(), meta.uid(), meta.gid(), current_uid)
+        })
+        .collect();
+    let system_dirs: Vec<PathBuf> = SYSTEM_DIRS
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .collect();
+    let is_system = |entry: &PathEntry| {
+        fs::canonicalize(&entry.dir).is_ok_and(|dir| system_dirs.contains(&dir))
+    };
+    let system_indexes: Vec<usize> = (0..entries.len())
+        .filter(|&index| is_system(&entries[index]))
+        .collect();
+
+    let mut findings = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let dir = search::normalize_dir(&entry.dir);
+        let mut report = |path: String, issue| findings.push(Finding { index, path, issue });
+
+        match entry.kind {
+            EntryKind::Empty => report("''".to_string(), Issue::Empty),
+            EntryKind::Relative => {
+                report(entry.raw.to_string_lossy().into_owned(), Issue::Relative)
+            }
+            EntryKind::Absolute | EntryKind::Tilde => {}
+        }
+
+        if let Some(writer) = writers[index] {
+            let next_system = system_indexes.iter().find(|&&system| system > index);
+            match next_system {
+                Some(&system) => report(
+                    dir.clone(),
+                    Issue::WritableBeforeSystem {
+                        writer,
+                        system: search::normalize_dir(&entries[system].dir),
+                    },
+                ),
+                None if matches!(writer, Writer::Group(_) | Writer::Others) => {
+                    report(dir.clone(), Issue::WritableDir(writer))
+                }
+                None => {}
+            }
+        }
+
+        for path in world_writable_files(&entry.dir) {
+            let display = path.display().to_string();
+            report(display, Issue::WorldWritableFile(path));
+        }
+    }
+
+    for name in CORE_UTILITIES {
+        let copies: Vec<usize> = (0..entries.len())
+            .filter(|&index| search::is_command(&entries[index].dir.join(name)))
+            .collect();
+        let Some((&winner, rest)) = copies.split_first() else {
+            continue;
+        };
+        let Some(writer) = writers[winner] else {
+            continue;
+        };
+        if let Some(&shadowed) = rest.iter().find(|&&index| writers[index].is_none()) {
+            findings.push(Finding {
+                index: winner,
+                path: entries[winner].dir.join(name).display().to_string(),
+                issue: Issue::ShadowsCoreUtility {
+                    writer,
+                    shadowed: entries[shadowed].dir.join(name),
+                },
+            });
+        }
+    }
+
+    // keep findings in PATH order; the sort is stable
+    findings.sort_by_key(|finding| finding.index);
+    findings
+}
+
+/// Regular files in `dir` (after following symlinks) that anyone can write.
+fn world_writable_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(files) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = files
+        .filter_map(Result::ok)
+        .map(|file| file.path())
+        .filter(|path| {
+            fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.mode() & 0o002 != 0)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_entry::Context;
+    use crate::test_util::TempDir;
+    use std::ffi::OsStr;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn parse(dir: &TempDir, list: &str) -> Vec<PathEntry> {
+        let context = Context {
+            cwd: Some(dir.path().to_path_buf()),
+            home: None,
+            expand_tilde: true,
+        };
+        context.parse(OsStr::new(list))
+    }
+
+    fn chmod(path: &Path, mode: u32) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn writer_classes() {
+        assert_eq!(writer(0o755, 0, 0, 1000), None);
+        assert_eq!(writer(0o777, 0, 0, 1000), Some(Writer::Others));
+        assert_eq!(writer(0o1777, 0, 0, 1000), Some(Writer::Others));
+        assert_eq!(writer(0o775, 0, 50, 1000), Some(Writer::Group(50)));
+        // root's group is as trusted as root
+        assert_eq!(writer(0o775, 0, 0, 1000), None);
+        assert_eq!(writer(0o755, 1000, 1000, 1000), Some(Writer::You));
+        // owners can make their directories writable again
+        assert_eq!(writer(0o555, 1000, 1000, 1000), Some(Writer::You));
+        assert_eq!(writer(0o755, 1001, 1001, 1000), Some(Writer::Owner(1001)));
+        assert_eq!(writer(0o755, 1001, 1001, 0), Some(Writer::Owner(1001)));
+    }
+
+    #[test]
+    fn relative_and_empty_entries() {
+        let dir = TempDir::new("audit-relative");
+        let entries = parse(&dir, "bin::");
+        let findings = audit(&entries, 0);
+        let issues: Vec<(usize, &Issue)> = findings.iter().map(|f| (f.index, &f.issue)).collect();
+        assert_eq!(issues[0], (0, &Issue::Relative));
+        assert_eq!(issues[1], (1, &Issue::Empty));
+        assert_eq!(
+            findings[0].to_string(),
+            "bin: relative entry runs commands from the current directory"
+        );
+    }
+
+    #[test]
+    fn world_writable_directory_before_system_directory() {
+        let dir = TempDir::new("audit-writable-dir");
+        chmod(dir.path(), 0o777);
+        let list = format!("{}:/usr/bin", dir.path().display());
+        let findings = audit(&parse(&dir, &list), 0);
+        chmod(dir.path(), 0o755);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].issue,
+            Issue::WritableBeforeSystem {
+                writer: Writer::Others,
+                system: "/usr/bin".to_string()
+            }
+        );
+        assert_eq!(findings[0].severity(), Severity::Error);
+        assert_eq!(
+            findings[0].hint(),
+            format!("chmod o-w {}", dir.path().display())
+        );
+    }
+
+    #[test]
+    fn world_writable_directory_after_system_directories() {
+        let dir = TempDir::new("audit-writable-after");
+        chmod(dir.path(), 0o777);
+        let list = format!("/usr/bin:{}", dir.path().display());
+        let findings = audit(&parse(&dir, &list), 0);
+        chmod(dir.path(), 0o755);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].issue, Issue::WritableDir(Writer::Others));
+        assert_eq!(findings[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn world_writable_files() {
+        let dir = TempDir::new("audit-writable-file");
+        let file = dir.touch("tool");
+        chmod(&file, 0o777);
+        dir.touch("safe");
+        // owned by the auditing user, but not before a system directory
+        let uid = fs::metadata(dir.path()).unwrap().uid();
+        let findings = audit(&parse(&dir, &dir.path().display().to_string()), uid);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].issue, Issue::WorldWritableFile(file.clone()));
+        assert_eq!(findings[0].hint(), format!("chmod o-w {}", file.display()));
+    }
+
+    #[test]
+    fn core_utility_shadowed_from_writable_directory() {
+        let shadowing = TempDir::new("audit-shadowing");
+        let system = TempDir::new("audit-shadowed");
+        for dir in [&shadowing, &system] {
+            chmod(&dir.touch("sudo"), 0o755);
+        }
+        chmod(shadowing.path(), 0o777);
+        let list = format!("{}:{}", shadowing.path().display(), system.path().display());
+        let findings = audit(&parse(&shadowing, &list), 0);
+        chmod(shadowing.path(), 0o755);
+
+        let shadows: Vec<&Finding> = findings
+            .iter()
+            .filter(|f| matches!(f.issue, Issue::ShadowsCoreUtility { .. }))
+            .collect();
+        // system is owned by root when run as root; otherwise it is writable
+        // by the tester and nothing is shadowed from a safe directory
+        if fs::metadata(system.path()).unwrap().uid() == 0 {
+            assert_eq!(shadows.len(), 1);
+            assert_eq!(shadows[0].index, 0);
+            assert_eq!(
+                shadows[0].issue,
+                Issue::ShadowsCoreUtility {
+                    writer: Writer::Others,
+                    shadowed: system.path().join("sudo")
+                }
+            );
+        } else {
+            assert!(shadows.is_empty());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Detection of broken PATH entries.
+//!
+//! A PATH entry is broken when it is a symlink whose chain ends at a target
+//! that does not exist, or when the current user cannot stat it (or one of
+//! the links along its chain). Such entries show up in directory listings
+//! but can never be executed.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of symlinks followed before giving up, matching the
+/// Linux kernel's limit (ELOOP).
+const MAX_SYMLINK_HOPS: usize = 40;
+
+#[derive(Debug)]
+pub enum Breakage {
+    /// The last path in the chain does not exist.
+    Dangling(Vec<PathBuf>),
+    /// The chain loops back on itself (or is too long to follow).
+    Loop(Vec<PathBuf>),
+    /// The last path in the chain could not be stat'ed or read.
+    Unreadable(Vec<PathBuf>, io::Error),
+}
+
+impl Breakage {
+    /// Symlink chain starting at the PATH entry, up to the point where it
+    /// breaks.
+    pub fn chain(&self) -> &[PathBuf] {
+        match self {
+            Breakage::Dangling(chain) | Breakage::Loop(chain) | Breakage::Unreadable(chain, _) => {
+                chain
+            }
+        }
+    }
+}
+
+impl fmt::Display for Breakage {
+    /// Describes why the chain is broken, without the chain itself.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakage::Dangling(_) => write!(f, "target does not exist"),
+            Breakage::Loop(_) => write!(f, "too many levels of symbolic links"),
+            Breakage::Unreadable(_, err) => write!(f, "cannot stat: {}", err),
+        }
+    }
+}
+
+/// Follow the symlink chain starting at `path`.
+///
+/// Returns `None` if the chain ends at an existing file (or if `path` itself
+/// has vanished since the directory was listed).
+pub fn check(path: &Path) -> Option<Breakage> {
+    let mut chain = vec![path.to_path_buf()];
+
+    loop {
+        let current = chain.last().expect("chain is never empty");
+        let meta = match fs::symlink_metadata(current) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if chain.len() == 1 {
+                    return None;
+                }
+                return Some(Breakage::Dangling(chain));
+            }
+            Err(err) => return Some(Breakage::Unreadable(chain, err)),
+        };
+
+        if !meta.file_type().is_symlink() {
+            return None;
+        }
+        if chain.len() > MAX_SYMLINK_HOPS {
+            return Some(Breakage::Loop(chain));
+        }
+
+        let target = match fs::read_link(current) {
+            Ok(target) => target,
+            Err(err) => return Some(Breakage::Unreadable(chain, err)),
+        };
+        // relative targets are relative to the directory containing the link
+        let next = match current.parent() {
+            Some(parent) if target.is_relative() => parent.join(target),
+            _ => target,
+        };
+        chain.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn regular_file_is_not_broken() {
+        let tmp = TempDir::new("broken-regular");
+        let file = tmp.touch("ls");
+        assert!(check(&file).is_none());
+    }
+
+    #[test]
+    fn missing_file_is_not_broken() {
+        let tmp = TempDir::new("broken-missing");
+        assert!(check(&tmp.path().join("gone")).is_none());
+    }
+
+    #[test]
+    fn valid_symlink_chain_is_not_broken() {
+        let tmp = TempDir::new("broken-valid-chain");
+        tmp.touch("real");
+        symlink("real", tmp.path().join("middle")).unwrap();
+        symlink(tmp.path().join("middle"), tmp.path().join("link")).unwrap();
+        assert!(check(&tmp.path().join("link")).is_none());
+    }
+
+    #[test]
+    fn dangling_symlink_reports_full_chain() {
+        let tmp = TempDir::new("broken-dangling");
+        symlink("missing", tmp.path().join("middle")).unwrap();
+        symlink("middle", tmp.path().join("link")).unwrap();
+
+        let breakage = check(&tmp.path().join("link")).unwrap();
+        assert!(matches!(breakage, Breakage::Dangling(_)));
+        assert_eq!(
+            breakage.chain(),
+            [
+                tmp.path().join("link"),
+                tmp.path().join("middle"),
+                tmp.path().join("missing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn symlink_loop_is_detected() {
+        let tmp = TempDir::new("broken-loop");
+        symlink("b", tmp.path().join("a")).unwrap();
+        symlink("a", tmp.path().join("b")).unwrap();
+
+        let breakage = check(&tmp.path().join("a")).unwrap();
+        assert!(matches!(breakage, Breakage::Loop(_)));
+        assert_eq!(breakage.chain().len(), MAX_SYMLINK_HOPS + 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! Rewriting a path list without dead weight.
+//!
+//! Installers tend to append to PATH without checking what is already
+//! there. `--emit-clean-path` drops the entries `doctor` finds useless and
+//! prints the result as a shell command, after checking that every command
+//! still resolves to the same file.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::doctor::{self, Finding, Problem};
+use crate::path_entry::{EntryKind, PathEntry};
+
+/// Shell syntax of the emitted command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    /// `export PATH='...'`, for bash, zsh and other POSIX shells
+    Sh,
+    /// `set -gx PATH '...' '...'`
+    Fish,
+}
+
+impl Syntax {
+    /// Syntax for the user's shell (`$SHELL`), defaulting to `Sh`.
+    pub fn from_shell(shell: Option<&OsStr>) -> Self {
+        match shell.and_then(|shell| Path::new(shell).file_name()) {
+            Some(name) if name == "fish" => Syntax::Fish,
+            _ => Syntax::Sh,
+        }
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" | "bash" | "zsh" => Ok(Syntax::Sh),
+            "fish" => Ok(Syntax::Fish),
+            _ => Err(format!(
+                "Invalid shell '{}'. Use 'bash', 'zsh', 'sh' or 'fish'",
+                s
+            )),
+        }
+    }
+}
+
+/// Entries of a path list split into the ones to keep and the findings that
+/// got the others removed.
+pub struct Cleaned<'a> {
+    pub kept: Vec<&'a PathEntry>,
+    pub removed: Vec<Finding>,
+}
+
+/// Drop nonexistent directories, non-directories, duplicates and aliases of
+/// earlier entries (`/bin` after `/usr/bin` on merged-/usr systems).
+///
+/// Entries that are not absolute are always kept, and never count as the
+/// original of a duplicate: what they refer to depends on the current
+/// directory.
+pub fn clean(entries: &[PathEntry], commands_only: bool) -> Cleaned<'_> {
+    let is_absolute = |index: usize| entries[index].kind == EntryKind::Absolute;
+    let mut removed: Vec<Finding> = doctor::check(entries, commands_only)
+        .into_iter()
+        .filter(|finding| {
+            is_absolute(finding.index)
+                && match finding.problem {
+                    Problem::Missing | Problem::NotADirectory => true,
+                    Problem::Duplicate(first) | Problem::SameDirectory(first, _) => {
+                        is_absolute(first)
+                    }
+                    _ => false,
+                }
+        })
+        .collect();
+    // an entry may have several findings; report it once
+    removed.dedup_by_key(|finding| finding.index);
+
+    let kept = entries
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !removed.iter().any(|finding| finding.index == *index))
+        .map(|(_, entry)| entry)
+        .collect();
+    Cleaned { kept, removed }
+}
+
+/// Entry as it should be written in the cleaned path list: as written, but
+/// without trailing slashes on absolute entries.
+fn clean_raw(entry: &PathEntry) -> &OsStr {
+    let mut raw = entry.raw.as_bytes();
+    if entry.kind == EntryKind::Absolute {
+        while raw.len() > 1 && raw.ends_with(b"/") {
+            raw = &raw[..raw.len() - 1];
+        }
+    }
+    OsStr::from_bytes(raw)
+}
+
+/// Shell command that sets `var` to `entries`, e.g.
+/// `export PATH='/usr/local/bin:/usr/bin'`. No entries is an error: an empty
+/// path list means the current directory to many shells and `execvp`.
+pub fn export_command(var: &str, entries: &[&PathEntry], syntax: Syntax) -> Result<String, String> {
+    if entries.is_empty() {
+        return Err(format!(
+            "Every entry of {0} would be removed; an empty {0} means the current directory, \
+             so no command is printed",
+            var
+        ));
+    }
+    let raws = entries.iter().map(|entry| clean_raw(entry));
+    let mut command = String::new();
+    match syntax {
+        Syntax::Sh => {
+            let mut list = OsString::new();
+            for (i, raw) in raws.enumerate() {
+                if i > 0 {
+                    list.push(":");
+                }
+                list.push(raw);
+            }
+            let _ = write!(command, "export {}={}", var, quote_sh(&list));
+        }
+        // fish keeps variables ending in PATH as lists
+        Syntax::Fish if var.ends_with("PATH") => {
+            let _ = write!(command, "set -gx {}", var);
+            for raw in raws {
+                let _ = write!(command, " {}", quote_fish(raw));
+            }
+        }
+        Syntax::Fish => {
+            let list = raws.collect::<Vec<_>>().join(OsStr::new(":"));
+            let _ = write!(command, "set -gx {} {}", var, quote_fish(&list));
+        }
+    }
+    Ok(command)
+}
+
+/// Single-quote `s` for POSIX shells.
+fn quote_sh(s: &OsStr) -> String {
+    format!("'{}'", s.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Single-quote `s` for fish, where `\` and `'` are escaped inside quotes.
+fn quote_fish(s: &OsStr) -> String {
+    let s = s.to_string_lossy();
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_entry::Context;
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn parse(cwd: &Path, list: &str) -> Vec<PathEntry> {
+        let context = Context {
+            cwd: Some(cwd.to_path_buf()),
+            home: None,
+            expand_tilde: true,
+        };
+        context.parse(OsStr::new(list))
+    }
+
+    fn executable_dir(dir: &TempDir, name: &str) -> PathBuf {
+        let sub = dir.path().join(name);
+        fs::create_dir(&sub).unwrap();
+        let tool = sub.join("tool");
+        fs::write(&tool, b"").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::canonicalize(sub).unwrap()
+    }
+
+    fn kept_raws(cleaned: &Cleaned) -> Vec<String> {
+        cleaned
+            .kept
+            .iter()
+            .map(|entry| entry.raw.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn removes_missing_duplicates_and_aliases() {
+        let dir = TempDir::new("clean-path");
+        let a = executable_dir(&dir, "a");
+        let b = executable_dir(&dir, "b");
+        std::os::unix::fs::symlink(&a, dir.path().join("alias")).unwrap();
+        let list = format!(
+            "{a}:{a}/missing:{b}:{a}/:{alias}",
+            a = a.display(),
+            b = b.display(),
+            alias = dir.path().join("alias").display()
+        );
+        let entries = parse(dir.path(), &list);
+        let cleaned = clean(&entries, true);
+
+        assert_eq!(
+            kept_raws(&cleaned),
+            [a.display().to_string(), b.display().to_string()]
+        );
+        let removed: Vec<usize> = cleaned.removed.iter().map(|f| f.index).collect();
+        assert_eq!(removed, [1, 3, 4]);
+    }
+
+    #[test]
+    fn keeps_relative_entries_and_their_duplicates() {
+        let dir = TempDir::new("clean-path-relative");
+        let a = executable_dir(&dir, "a");
+        // `a` is the same directory as the absolute entry from this cwd only
+        let list = format!("a:{}::", a.display());
+        let entries = parse(dir.path(), &list);
+        let cleaned = clean(&entries, true);
+        assert_eq!(cleaned.kept.len(), 4);
+        assert!(cleaned.removed.is_empty());
+    }
+
+    #[test]
+    fn export_command_sh() {
+        let entries = parse(Path::new("/"), "/usr/bin/:/opt/it's:");
+        let entries: Vec<&PathEntry> = entries.iter().collect();
+        assert_eq!(
+            export_command("PATH", &entries, Syntax::Sh).unwrap(),
+            r"export PATH='/usr/bin:/opt/it'\''s:'"
+        );
+    }
+
+    #[test]
+    fn export_command_fish() {
+        let entries = parse(Path::new("/"), r"/usr/bin:/opt/it's\x");
+        let entries: Vec<&PathEntry> = entries.iter().collect();
+        assert_eq!(
+            export_command("PATH", &entries, Syntax::Fish).unwrap(),
+            r"set -gx PATH '/usr/bin' '/opt/it\'s\\x'"
+        );
+        assert_eq!(
+            export_command("XDG_DATA_DIRS", &entries, Syntax::Fish).unwrap(),
+            r"set -gx XDG_DATA_DIRS '/usr/bin:/opt/it\'s\\x'"
+        );
+    }
+
+    #[test]
+    fn export_command_refuses_empty_list() {
+        let dir = TempDir::new("clean-all-missing");
+        let entries = parse(dir.path(), "/nonexistent:/also/not");
+        let cleaned = clean(&entries, true);
+        assert!(cleaned.kept.is_empty());
+        assert_eq!(cleaned.removed.len(), 2);
+        for syntax in [Syntax::Sh, Syntax::Fish] {
+            let err = export_command("PATH", &cleaned.kept, syntax).unwrap_err();
+            assert!(
+                err.starts_with("Every entry of PATH would be removed"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn syntax_from_shell() {
+        assert_eq!(
+            Syntax::from_shell(Some(OsStr::new("/usr/bin/fish"))),
+            Syntax::Fish
+        );
+        assert_eq!(Syntax::from_shell(Some(OsStr::new("/bin/zsh"))), Syntax::Sh);
+        assert_eq!(Syntax::from_shell(None), Syntax::Sh);
+        assert_eq!("bash".parse(), Ok(Syntax::Sh));
+        assert!("csh".parse::<Syntax>().is_err());
+    }
+}
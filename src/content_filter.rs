@@ -0,0 +1,435 @@
+//! Filtering matches by what is inside the file.
+//!
+//! Name patterns are checked while directories are listed; these predicates
+//! open each file that passes them, so they only run on the matches of the
+//! name pattern.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::dir_reader::DirReader;
+use crate::elf::Elf;
+use crate::filename_filter::{FileNameFilter, FilterResult};
+use crate::shebang::Shebang;
+
+/// Bytes of a file `--contains` searches; the rest is ignored.
+const MAX_CONTENT_SIZE: u64 = 16 << 20;
+/// Bytes at the start of a file checked for a NUL byte, which makes it
+/// binary (as with grep).
+const BINARY_CHECK_SIZE: usize = 8192;
+/// Characters of a matched line shown in the note
+const MAX_LINE_DISPLAY: usize = 100;
+
+/// Predicates on file contents. A file must pass all of the given ones.
+#[derive(Default)]
+pub struct ContentFilter {
+    /// Pattern for the `DT_NEEDED` libraries of ELF files
+    pub links_against: Option<Box<dyn FileNameFilter>>,
+    /// Name of a dynamic symbol ELF files must import
+    pub imports: Option<String>,
+    /// Pattern for the program running a script
+    pub interpreter: Option<Box<dyn FileNameFilter>>,
+    /// Directories `#!/usr/bin/env` lines look up commands in
+    pub dirs: Vec<PathBuf>,
+    /// Pattern for a line of the file
+    pub contains: Option<Box<dyn FileNameFilter>>,
+    /// Search binary files with `contains` too
+    pub binary: bool,
+}
+
+impl ContentFilter {
+    /// Whether no predicates are given, so every file passes.
+    pub fn is_empty(&self) -> bool {
+        self.links_against.is_none()
+            && self.imports.is_none()
+            && self.interpreter.is_none()
+            && self.contains.is_none()
+    }
+
+    /// Notes on why the file at `path` passes, e.g. `links libssl.so.3` or
+    /// `interpreter /usr/bin/python2.7`, or `None` if it does not. `env`
+    /// lines look up their command in the directories `reader` still
+    /// answers for.
+    pub fn check(&self, path: &Path, reader: &DirReader) -> Option<Vec<String>> {
+        let mut notes = Vec::new();
+        if let Some(pattern) = &self.links_against {
+            let elf = Elf::read(path).ok()??;
+            let linked: Vec<&String> = elf
+                .needed
+                .iter()
+                .filter(|name| matches!(pattern.filter(name), FilterResult::Matched(_)))
+                .collect();
+            if linked.is_empty() {
+                return None;
+            }
+            for name in linked {
+                notes.push(format!("links {}", name));
+            }
+        }
+        if let Some(symbol) = &self.imports {
+            let imports = Elf::read_imports(path).ok()??;
+            if !imports.contains(symbol) {
+                return None;
+            }
+            notes.push(format!("imports {}", symbol));
+        }
+        if let Some(pattern) = &self.interpreter {
+            let program = self.interpreter(path, pattern.as_ref(), reader)?;
+            notes.push(format!("interpreter {}", program.display()));
+        }
+        if let Some(pattern) = &self.contains {
+            notes.push(self.contains(path, pattern.as_ref())?);
+        }
+        Some(notes)
+    }
+
+    /// Where the file at `path` first matches `pattern`: `line N: text`, or
+    /// `binary file matches`. Lines are read one at a time, up to
+    /// `MAX_CONTENT_SIZE` bytes.
+    fn contains(&self, path: &Path, pattern: &dyn FileNameFilter) -> Option<String> {
+        // opening a FIFO would block
+        if !fs::metadata(path).ok()?.is_file() {
+            return None;
+        }
+        let file = File::open(path).ok()?;
+        let mut reader = BufReader::with_capacity(BINARY_CHECK_SIZE, file.take(MAX_CONTENT_SIZE));
+        let binary = reader.fill_buf().ok()?.contains(&0);
+        if binary && !self.binary {
+            return None;
+        }
+
+        let mut line = Vec::new();
+        for number in 1.. {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).ok()? == 0 {
+                break;
+            }
+            // without the terminator, so `$` anchors match LF and CRLF lines
+            let end = line.strip_suffix(b"\n").unwrap_or(&line);
+            let end = end.strip_suffix(b"\r").unwrap_or(end);
+            let text = String::from_utf8_lossy(end);
+            if !matches!(pattern.filter(&text), FilterResult::Matched(_)) {
+                continue;
+            }
+            if binary {
+                return Some("binary file matches".to_string());
+            }
+            let text = text.trim();
+            return Some(match text.char_indices().nth(MAX_LINE_DISPLAY) {
+                Some((end, _)) => format!("line {}: {}...", number, &text[..end]),
+                None => format!("line {}: {}", number, text),
+            });
+        }
+        None
+    }
+
+    /// Program running the script at `path` if its file name matches
+    /// `pattern`. The target of a symlinked interpreter matches too, so
+    /// `python2` finds scripts run by a `python` that links to `python2.7`.
+    /// Directories are not matched, so `bin` does not find every script.
+    fn interpreter(
+        &self,
+        path: &Path,
+        pattern: &dyn FileNameFilter,
+        reader: &DirReader,
+    ) -> Option<PathBuf> {
+        // binaries are rejected by their first two bytes
+        let program = Shebang::read(path).ok()??.program(&self.dirs, reader)?;
+        let target = fs::canonicalize(&program).ok();
+        [Some(&program), target.as_ref()]
+            .into_iter()
+            .flatten()
+            .find(|candidate| {
+                candidate.file_name().is_some_and(|name| {
+                    matches!(
+                        pattern.filter(&name.to_string_lossy()),
+                        FilterResult::Matched(_)
+                    )
+                })
+            })
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filename_filter::{RegexFilter, SubstringFilter};
+    use crate::test_util::{ElfSpec, TempDir};
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    fn links_against(filter: impl FileNameFilter + 'static) -> ContentFilter {
+        ContentFilter {
+            links_against: Some(Box::new(filter)),
+            ..ContentFilter::default()
+        }
+    }
+
+    #[test]
+    fn empty_filter_passes_everything() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-empty");
+        let file = dir.touch("notes.txt");
+        let filter = ContentFilter::default();
+        assert!(filter.is_empty());
+        assert_eq!(filter.check(&file, &reader), Some(vec![]));
+    }
+
+    #[test]
+    fn links_against_matches_needed_libraries() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-links");
+        let curl = dir.path().join("curl");
+        let spec = ElfSpec {
+            needed: &["libssl.so.1.1", "libcrypto.so.1.1", "libc.so.6"],
+            ..ElfSpec::default()
+        };
+        fs::write(&curl, spec.build()).unwrap();
+        let script = dir.path().join("script");
+        fs::write(&script, "#!/bin/sh\nlibssl.so.1.1\n").unwrap();
+
+        let filter = links_against(SubstringFilter::new("libssl.so.1.1"));
+        assert!(!filter.is_empty());
+        assert_eq!(
+            filter.check(&curl, &reader),
+            Some(vec!["links libssl.so.1.1".to_string()])
+        );
+        assert_eq!(filter.check(&script, &reader), None);
+        assert_eq!(filter.check(&dir.path().join("missing"), &reader), None);
+
+        let filter = links_against(RegexFilter::new(r"^lib(ssl|crypto)\.so\.3$").unwrap());
+        assert_eq!(filter.check(&curl, &reader), None);
+        let filter = links_against(RegexFilter::new(r"^lib(ssl|crypto)\.").unwrap());
+        assert_eq!(
+            filter.check(&curl, &reader),
+            Some(vec![
+                "links libssl.so.1.1".to_string(),
+                "links libcrypto.so.1.1".to_string()
+            ])
+        );
+    }
+
+    fn interpreter(filter: impl FileNameFilter + 'static, dirs: &[PathBuf]) -> ContentFilter {
+        ContentFilter {
+            interpreter: Some(Box::new(filter)),
+            dirs: dirs.to_vec(),
+            ..ContentFilter::default()
+        }
+    }
+
+    #[test]
+    fn interpreter_matches_scripts() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-interpreter");
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let python = bin.join("python2.7");
+        fs::write(&python, "").unwrap();
+        fs::set_permissions(&python, fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("python2.7", bin.join("python")).unwrap();
+
+        let env_script = dir.path().join("env-script");
+        fs::write(&env_script, "#!/usr/bin/env python\nprint 'hi'\n").unwrap();
+        let direct = dir.path().join("direct");
+        fs::write(&direct, "#!/usr/bin/python3 -u\n").unwrap();
+        let binary = dir.path().join("binary");
+        fs::write(&binary, ElfSpec::default().build()).unwrap();
+
+        let dirs = [bin.clone()];
+        let filter = interpreter(SubstringFilter::new("python2"), &dirs);
+        assert!(!filter.is_empty());
+        // env finds the symlink, which points to python2.7
+        assert_eq!(
+            filter.check(&env_script, &reader),
+            Some(vec![format!("interpreter {}", python.display())])
+        );
+        assert_eq!(filter.check(&direct, &reader), None);
+        assert_eq!(filter.check(&binary, &reader), None);
+        // without the directory, env cannot resolve the command
+        assert_eq!(
+            interpreter(SubstringFilter::new("python2"), &[]).check(&env_script, &reader),
+            None
+        );
+
+        let filter = interpreter(RegexFilter::new("python[23]?$").unwrap(), &dirs);
+        assert_eq!(
+            filter.check(&env_script, &reader),
+            Some(vec![format!(
+                "interpreter {}",
+                bin.join("python").display()
+            )])
+        );
+        assert_eq!(
+            filter.check(&direct, &reader),
+            Some(vec!["interpreter /usr/bin/python3".to_string()])
+        );
+    }
+
+    #[test]
+    fn interpreter_ignores_directories() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-interpreter-dirs");
+        let env_bin = dir.path().join("python2-env").join("bin");
+        fs::create_dir_all(&env_bin).unwrap();
+        let python = env_bin.join("python3");
+        fs::write(&python, "").unwrap();
+        fs::set_permissions(&python, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let script = dir.path().join("script");
+        fs::write(&script, format!("#!{}\n", python.display())).unwrap();
+
+        // python2 and bin only appear in the directories
+        for pattern in ["python2", "bin"] {
+            assert_eq!(
+                interpreter(SubstringFilter::new(pattern), &[]).check(&script, &reader),
+                None
+            );
+        }
+        assert_eq!(
+            interpreter(RegexFilter::new("^python3$").unwrap(), &[]).check(&script, &reader),
+            Some(vec![format!("interpreter {}", python.display())])
+        );
+    }
+
+    fn contains(filter: impl FileNameFilter + 'static, binary: bool) -> ContentFilter {
+        ContentFilter {
+            contains: Some(Box::new(filter)),
+            binary,
+            ..ContentFilter::default()
+        }
+    }
+
+    #[test]
+    fn contains_shows_first_matching_line() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-contains");
+        let wrapper = dir.path().join("deploy");
+        fs::write(
+            &wrapper,
+            "#!/bin/sh\n  rsync -a . old-host.example.com:/srv  \nssh old-host.example.com\n",
+        )
+        .unwrap();
+
+        let filter = contains(SubstringFilter::new("old-host"), false);
+        assert!(!filter.is_empty());
+        assert_eq!(
+            filter.check(&wrapper, &reader),
+            Some(vec![
+                "line 2: rsync -a . old-host.example.com:/srv".to_string()
+            ])
+        );
+        let filter = contains(RegexFilter::new(r"^ssh\b").unwrap(), false);
+        assert_eq!(
+            filter.check(&wrapper, &reader),
+            Some(vec!["line 3: ssh old-host.example.com".to_string()])
+        );
+        assert_eq!(
+            contains(SubstringFilter::new("new-host"), false).check(&wrapper, &reader),
+            None
+        );
+        // directories have no contents
+        assert_eq!(
+            contains(SubstringFilter::new(""), false).check(dir.path(), &reader),
+            None
+        );
+    }
+
+    #[test]
+    fn contains_anchors_match_without_line_endings() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-line-endings");
+        let unix = dir.path().join("unix");
+        fs::write(&unix, "#!/bin/sh\nexec old-tool\n").unwrap();
+        let dos = dir.path().join("dos");
+        fs::write(&dos, "#!/bin/sh\r\nexec old-tool\r\n").unwrap();
+
+        let filter = contains(RegexFilter::new(r"^exec old-tool$").unwrap(), false);
+        for file in [&unix, &dos] {
+            assert_eq!(
+                filter.check(file, &reader),
+                Some(vec!["line 2: exec old-tool".to_string()])
+            );
+        }
+        let filter = contains(RegexFilter::new(r"^#!/bin/sh$").unwrap(), false);
+        assert_eq!(
+            filter.check(&dos, &reader),
+            Some(vec!["line 1: #!/bin/sh".to_string()])
+        );
+    }
+
+    #[test]
+    fn contains_shortens_long_lines() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-long-line");
+        let file = dir.path().join("minified");
+        fs::write(
+            &file,
+            format!("{}needle{}", "é".repeat(98), "x".repeat(500)),
+        )
+        .unwrap();
+        let note = contains(SubstringFilter::new("needle"), false)
+            .check(&file, &reader)
+            .unwrap()
+            .remove(0);
+        assert_eq!(note, format!("line 1: {}ne...", "é".repeat(98)));
+    }
+
+    #[test]
+    fn contains_skips_binaries_unless_asked() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-binary");
+        let binary = dir.path().join("tool");
+        fs::write(
+            &binary,
+            b"\x7fELF\x00\x00\nconnect old-host.example.com\x00\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            contains(SubstringFilter::new("old-host"), false).check(&binary, &reader),
+            None
+        );
+        assert_eq!(
+            contains(SubstringFilter::new("old-host"), true).check(&binary, &reader),
+            Some(vec!["binary file matches".to_string()])
+        );
+    }
+
+    #[test]
+    fn imports_matches_whole_symbol_names() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("content-imports");
+        let tool = dir.path().join("tool");
+        let spec = ElfSpec {
+            needed: &["libssl.so.3"],
+            imports: &["SSL_read_ex"],
+            exports: &["SSL_read"],
+            ..ElfSpec::default()
+        };
+        fs::write(&tool, spec.build()).unwrap();
+
+        let imports = |symbol: &str| ContentFilter {
+            imports: Some(symbol.to_string()),
+            ..ContentFilter::default()
+        };
+        assert_eq!(
+            imports("SSL_read_ex").check(&tool, &reader),
+            Some(vec!["imports SSL_read_ex".to_string()])
+        );
+        // defined, not imported
+        assert_eq!(imports("SSL_read").check(&tool, &reader), None);
+
+        let both = ContentFilter {
+            imports: Some("SSL_read_ex".to_string()),
+            ..links_against(SubstringFilter::new("libssl"))
+        };
+        assert_eq!(
+            both.check(&tool, &reader),
+            Some(vec![
+                "links libssl.so.3".to_string(),
+                "imports SSL_read_ex".to_string()
+            ])
+        );
+    }
+}
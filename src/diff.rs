@@ -0,0 +1,194 @@
+//! Comparison of command resolution between two PATH values.
+//!
+//! Answers "what changes if I switch from this PATH to that one", e.g.
+//! before and after activating a virtualenv, and "what happens if I add
+//! these directories to PATH".
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// How the resolution of a single command name changed.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// The name only resolves with the new PATH.
+    Added { name: String, new: PathBuf },
+    /// The name only resolves with the old PATH.
+    Removed { name: String, old: PathBuf },
+    /// The name resolves to a different file with the new PATH.
+    Changed {
+        name: String,
+        old: PathBuf,
+        new: PathBuf,
+    },
+}
+
+/// Compare two resolutions (name to winning path), as produced by
+/// `search::resolve`. Changes are sorted by name.
+pub fn diff(old: &BTreeMap<String, PathBuf>, new: &BTreeMap<String, PathBuf>) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (name, old_path) in old {
+        match new.get(name) {
+            None => changes.push(Change::Removed {
+                name: name.clone(),
+                old: old_path.clone(),
+            }),
+            Some(new_path) if new_path != old_path => changes.push(Change::Changed {
+                name: name.clone(),
+                old: old_path.clone(),
+                new: new_path.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (name, new_path) in new {
+        if !old.contains_key(name) {
+            changes.push(Change::Added {
+                name: name.clone(),
+                new: new_path.clone(),
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.name().cmp(b.name()));
+    changes
+}
+
+/// A command in an added directory that loses to an existing command.
+#[derive(Debug, PartialEq)]
+pub struct Shadowed {
+    pub name: String,
+    /// The copy in the added directory
+    pub path: PathBuf,
+    /// The existing copy that still wins
+    pub by: PathBuf,
+}
+
+/// Find the commands in `added` (name to path, for the added directories)
+/// that are shadowed by an existing command once the directories are added.
+///
+/// `old` and `new` are the resolutions without and with the added
+/// directories. Sorted by name.
+pub fn shadowed(
+    added: &BTreeMap<String, PathBuf>,
+    old: &BTreeMap<String, PathBuf>,
+    new: &BTreeMap<String, PathBuf>,
+) -> Vec<Shadowed> {
+    added
+        .iter()
+        .filter_map(|(name, path)| {
+            let winner = new.get(name)?;
+            // only report losses to a command that was already in PATH
+            if winner == path || old.get(name) != Some(winner) {
+                return None;
+            }
+            Some(Shadowed {
+                name: name.clone(),
+                path: path.clone(),
+                by: winner.clone(),
+            })
+        })
+        .collect()
+}
+
+impl Change {
+    pub fn name(&self) -> &str {
+        match self {
+            Change::Added { name, .. }
+            | Change::Removed { name, .. }
+            | Change::Changed { name, .. } => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolution(entries: &[(&str, &str)]) -> BTreeMap<String, PathBuf> {
+        entries
+            .iter()
+            .map(|(name, path)| (name.to_string(), PathBuf::from(path)))
+            .collect()
+    }
+
+    #[test]
+    fn identical_resolutions_have_no_changes() {
+        let old = resolution(&[("ls", "/usr/bin/ls"), ("python3", "/usr/bin/python3")]);
+        assert!(diff(&old, &old.clone()).is_empty());
+    }
+
+    #[test]
+    fn added_removed_and_changed() {
+        let old = resolution(&[
+            ("ls", "/usr/bin/ls"),
+            ("python3", "/usr/bin/python3"),
+            ("deactivate", "/opt/old/bin/deactivate"),
+        ]);
+        let new = resolution(&[
+            ("activate", "/opt/venv/bin/activate"),
+            ("ls", "/usr/bin/ls"),
+            ("python3", "/opt/venv/bin/python3"),
+        ]);
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Added {
+                    name: "activate".to_string(),
+                    new: PathBuf::from("/opt/venv/bin/activate"),
+                },
+                Change::Removed {
+                    name: "deactivate".to_string(),
+                    old: PathBuf::from("/opt/old/bin/deactivate"),
+                },
+                Change::Changed {
+                    name: "python3".to_string(),
+                    old: PathBuf::from("/usr/bin/python3"),
+                    new: PathBuf::from("/opt/venv/bin/python3"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_by_existing_commands() {
+        // /opt/vendor/bin appended after /usr/bin
+        let added = resolution(&[
+            ("make", "/opt/vendor/bin/make"),
+            ("vendor-cc", "/opt/vendor/bin/vendor-cc"),
+        ]);
+        let old = resolution(&[("make", "/usr/bin/make"), ("ls", "/usr/bin/ls")]);
+        let new = resolution(&[
+            ("make", "/usr/bin/make"),
+            ("ls", "/usr/bin/ls"),
+            ("vendor-cc", "/opt/vendor/bin/vendor-cc"),
+        ]);
+
+        assert_eq!(
+            shadowed(&added, &old, &new),
+            vec![Shadowed {
+                name: "make".to_string(),
+                path: PathBuf::from("/opt/vendor/bin/make"),
+                by: PathBuf::from("/usr/bin/make"),
+            }]
+        );
+    }
+
+    #[test]
+    fn shadowed_ignores_added_commands_that_win() {
+        // /opt/vendor/bin prepended before /usr/bin
+        let added = resolution(&[("ld", "/opt/vendor/bin/ld")]);
+        let old = resolution(&[("ld", "/usr/bin/ld")]);
+        let new = resolution(&[("ld", "/opt/vendor/bin/ld")]);
+        assert!(shadowed(&added, &old, &new).is_empty());
+    }
+
+    #[test]
+    fn shadowed_ignores_conflicts_between_added_directories() {
+        let added = resolution(&[("tool", "/opt/b/tool")]);
+        let old = resolution(&[]);
+        let new = resolution(&[("tool", "/opt/a/tool")]);
+        assert!(shadowed(&added, &old, &new).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! Directory listing with timeouts.
+//!
+//! A hung NFS automount blocks `read_dir` forever. With a timeout, each
+//! directory is listed on its own thread, and one that does not answer in
+//! time is abandoned so the search can go on with the rest of the path list.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Source of directory listings: the filesystem, or a fake one in tests.
+pub trait DirSource: Send + Sync {
+    /// Names of the files in `dir`, in directory order.
+    fn list(&self, dir: &Path) -> io::Result<Vec<OsString>>;
+}
+
+/// Lists directories with `fs::read_dir`.
+pub struct Filesystem;
+
+impl DirSource for Filesystem {
+    fn list(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        let mut names = Vec::new();
+        for file in fs::read_dir(dir)? {
+            match file {
+                Ok(dir_entry) => names.push(dir_entry.file_name()),
+                Err(err) => eprintln!(
+                    "Failed to get directory entry in '{}': {}",
+                    dir.display(),
+                    err
+                ),
+            }
+        }
+        Ok(names)
+    }
+}
+
+/// How listing a directory ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// Listed, with this many files
+    Listed(usize),
+    Failed(io::Error),
+    /// Abandoned after the timeout
+    TimedOut,
+}
+
+/// How long listing a directory took.
+#[derive(Debug)]
+pub struct Timing {
+    pub dir: PathBuf,
+    pub elapsed: Duration,
+    pub outcome: Outcome,
+}
+
+/// Lists directories, each at most once, remembering how long each took.
+///
+/// Modes that resolve several path lists (`diff`, `--prepend`) share
+/// directories between them; those are only listed, and only time out,
+/// once.
+pub struct DirReader {
+    source: Arc<dyn DirSource>,
+    timeout: Option<Duration>,
+    listings: RefCell<HashMap<PathBuf, Option<Vec<OsString>>>>,
+    /// Directories that did not answer in time
+    abandoned: RefCell<HashSet<PathBuf>>,
+    timings: RefCell<Vec<Timing>>,
+}
+
+impl DirReader {
+    /// Reader of the real filesystem that gives up on a directory after
+    /// `timeout`, if given.
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self::with_source(Arc::new(Filesystem), timeout)
+    }
+
+    pub fn with_source(source: Arc<dyn DirSource>, timeout: Option<Duration>) -> Self {
+        DirReader {
+            source,
+            timeout,
+            listings: RefCell::new(HashMap::new()),
+            abandoned: RefCell::new(HashSet::new()),
+            timings: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Names of the files in `dir`, or `None` if it cannot be read or did
+    /// not answer in time.
+    pub fn list(&self, dir: &Path) -> Option<Vec<OsString>> {
+        if let Some(names) = self.listings.borrow().get(dir) {
+            return names.clone();
+        }
+
+        let start = Instant::now();
+        let outcome = match self.timeout {
+            None => self.source.list(dir).map_err(Some),
+            Some(timeout) => self.list_with_timeout(dir, timeout),
+        };
+        let elapsed = start.elapsed();
+
+        let (names, outcome) = match outcome {
+            Ok(names) => {
+                let count = names.len();
+                (Some(names), Outcome::Listed(count))
+            }
+            Err(Some(err)) => (None, Outcome::Failed(err)),
+            Err(None) => {
+                self.abandoned.borrow_mut().insert(dir.to_path_buf());
+                (None, Outcome::TimedOut)
+            }
+        };
+        self.timings.borrow_mut().push(Timing {
+            dir: dir.to_path_buf(),
+            elapsed,
+            outcome,
+        });
+        self.listings
+            .borrow_mut()
+            .insert(dir.to_path_buf(), names.clone());
+        names
+    }
+
+    /// Whether `dir` answers in time, listing it first if it has not been
+    /// listed yet. Anything else that touches files in a directory asks
+    /// this first, so a hung mount costs one timeout, not a hang.
+    pub fn answers(&self, dir: &Path) -> bool {
+        self.list(dir);
+        !self.abandoned.borrow().contains(dir)
+    }
+
+    /// List `dir` on a separate thread. If that takes longer than
+    /// `timeout`, a warning is printed and the thread is left behind; the
+    /// error is then `None`.
+    fn list_with_timeout(
+        &self,
+        dir: &Path,
+        timeout: Duration,
+    ) -> Result<Vec<OsString>, Option<io::Error>> {
+        let (sender, receiver) = mpsc::channel();
+        let source = Arc::clone(&self.source);
+        let thread_dir = dir.to_path_buf();
+        thread::spawn(move || {
+            let _ = sender.send(source.list(&thread_dir));
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result.map_err(Some),
+            Err(RecvTimeoutError::Timeout) => {
+                eprintln!(
+                    "Warning: skipped '{}', which did not answer within {}",
+                    dir.display(),
+                    format_duration(timeout)
+                );
+                Err(None)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(Some(io::Error::other("listing thread panicked")))
+            }
+        }
+    }
+
+    /// Timings of every directory listed so far, in the order they were
+    /// listed.
+    pub fn take_timings(&self) -> Vec<Timing> {
+        self.timings.take()
+    }
+}
+
+/// Parse a duration such as `500ms`, `2s` or `1.5s`. Zero is rejected, as
+/// no directory answers that fast.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}'. Use e.g. '500ms' or '2s'", s);
+    let (number, unit_secs) = if let Some(number) = s.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = s.strip_suffix('s') {
+        (number, 1.0)
+    } else {
+        return Err(invalid());
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    match Duration::try_from_secs_f64(number * unit_secs) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(invalid()),
+    }
+}
+
+/// Format a duration in milliseconds, e.g. `12.3ms`.
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::FakeSource;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn slow_directory_is_abandoned() {
+        let (source, _release) = FakeSource::new();
+        let reader = DirReader::with_source(source, Some(Duration::from_millis(50)));
+        assert_eq!(reader.list(Path::new("/slow")), None);
+        assert_eq!(
+            reader.list(Path::new("/fast")),
+            Some(vec![OsString::from("fast-tool")])
+        );
+    }
+
+    #[test]
+    fn only_abandoned_directories_do_not_answer() {
+        let (source, _release) = FakeSource::new();
+        let reader = DirReader::with_source(source, Some(Duration::from_millis(50)));
+        assert!(!reader.answers(Path::new("/slow")));
+        assert!(reader.answers(Path::new("/fast")));
+        // failing to list is for the caller to find out
+        assert!(reader.answers(Path::new("/missing")));
+    }
+
+    #[test]
+    fn directories_are_listed_once() {
+        let (source, _release) = FakeSource::new();
+        let reader = DirReader::with_source(source.clone(), None);
+        reader.list(Path::new("/a"));
+        reader.list(Path::new("/missing"));
+        reader.list(Path::new("/a"));
+        reader.list(Path::new("/missing"));
+        assert_eq!(source.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn timings_record_outcomes_in_order() {
+        let (source, _release) = FakeSource::new();
+        let reader = DirReader::with_source(source, Some(Duration::from_millis(50)));
+        for dir in ["/a", "/slow", "/missing"] {
+            reader.list(Path::new(dir));
+        }
+        let timings = reader.take_timings();
+        let dirs: Vec<&Path> = timings.iter().map(|t| t.dir.as_path()).collect();
+        assert_eq!(
+            dirs,
+            [Path::new("/a"), Path::new("/slow"), Path::new("/missing")]
+        );
+        assert!(matches!(timings[0].outcome, Outcome::Listed(1)));
+        assert!(matches!(timings[1].outcome, Outcome::TimedOut));
+        assert!(timings[1].elapsed >= Duration::from_millis(50));
+        assert!(matches!(timings[2].outcome, Outcome::Failed(_)));
+        assert!(reader.take_timings().is_empty());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("500").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("0ms").is_err());
+        assert!(parse_duration("0.0000000001s").is_err());
+    }
+
+    #[test]
+    fn format_milliseconds() {
+        assert_eq!(format_duration(Duration::from_micros(12_345)), "12.3ms");
+    }
+}
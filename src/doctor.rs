@@ -0,0 +1,394 @@
+//! Linting of path lists.
+//!
+//! `pathsearch doctor` checks every entry of the path list for problems that
+//! searching hides: directories that do not exist or cannot be read,
+//! duplicates, entries whose meaning depends on the current directory, and
+//! directories on mounts that cannot run programs or may be slow.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use crate::mounts::{Mount, Mounts};
+use crate::path_entry::{EntryKind, PathEntry};
+use crate::search;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    /// Empty entry, which means the current directory
+    Empty,
+    /// Relative entry, which is resolved against the current directory
+    Relative,
+    /// Entry starting with `~`, which only bash expands
+    Tilde,
+    TrailingSlash,
+    /// Same entry as an earlier one
+    Duplicate(usize),
+    /// Different entry that resolves to the same directory as an earlier
+    /// one, e.g. `/bin` and `/usr/bin` on merged-/usr systems
+    SameDirectory(usize, PathBuf),
+    Missing,
+    NotADirectory,
+    /// The directory (or its mount) cannot be stat'ed or listed
+    Unreadable(io::Error),
+    /// The directory contains no executable files
+    NoExecutables,
+    /// The directory is on a mount where nothing can be executed
+    NoExecMount(PathBuf),
+    /// The directory is on a network filesystem of this type, mounted at
+    /// this path
+    NetworkMount(String, PathBuf),
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Problem::Empty
+            | Problem::Relative
+            | Problem::NotADirectory
+            | Problem::Unreadable(_) => Severity::Error,
+            Problem::Tilde
+            | Problem::Duplicate(_)
+            | Problem::Missing
+            | Problem::NoExecutables
+            | Problem::NoExecMount(_) => Severity::Warning,
+            Problem::TrailingSlash | Problem::SameDirectory(..) | Problem::NetworkMount(..) => {
+                Severity::Note
+            }
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Empty => write!(f, "empty entry means the current directory"),
+            Problem::Relative => write!(f, "relative entry depends on the current directory"),
+            Problem::Tilde => write!(f, "'~' is only expanded by bash"),
+            Problem::TrailingSlash => write!(f, "trailing slash"),
+            Problem::Duplicate(index) => write!(f, "duplicate of [{}]", index),
+            Problem::SameDirectory(index, canonical) => {
+                write!(f, "same directory as [{}] ({})", index, canonical.display())
+            }
+            Problem::Missing => write!(f, "does not exist"),
+            Problem::NotADirectory => write!(f, "not a directory"),
+            Problem::Unreadable(err) => write!(f, "cannot be read: {}", err),
+            Problem::NoExecutables => write!(f, "contains nothing executable"),
+            Problem::NoExecMount(mount_point) => write!(
+                f,
+                "on a noexec mount ({}); nothing in it can run",
+                mount_point.display()
+            ),
+            Problem::NetworkMount(fs_type, mount_point) => write!(
+                f,
+                "on a network filesystem ({} at {}); lookups may be slow",
+                fs_type,
+                mount_point.display()
+            ),
+        }
+    }
+}
+
+/// A problem with the entry at `index` of the path list.
+#[derive(Debug)]
+pub struct Finding {
+    pub index: usize,
+    pub problem: Problem,
+}
+
+/// Check every entry of a path list, returning findings in list order.
+///
+/// `commands_only` enables the checks that only make sense for PATH, where
+/// entries must contain executables.
+pub fn check(entries: &[PathEntry], commands_only: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen = HashMap::new();
+    let mut seen_canonical = HashMap::new();
+    let mounts = Mounts::load();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let mut report = |problem| findings.push(Finding { index, problem });
+
+        match entry.kind {
+            EntryKind::Absolute => {}
+            EntryKind::Empty => report(Problem::Empty),
+            EntryKind::Relative => report(Problem::Relative),
+            EntryKind::Tilde if commands_only => report(Problem::Tilde),
+            EntryKind::Tilde => {}
+        }
+        let raw = entry.raw.as_bytes();
+        if raw.len() > 1 && raw.ends_with(b"/") {
+            report(Problem::TrailingSlash);
+        }
+
+        let dir = search::normalize_dir(&entry.dir);
+        if let Some(&first) = seen.get(&dir) {
+            report(Problem::Duplicate(first));
+            continue;
+        }
+        seen.insert(dir, index);
+
+        if let Some(problem) = check_dir(entry, commands_only) {
+            report(problem);
+            continue;
+        }
+        if let Some(mount) = mounts.find(&entry.dir) {
+            mount_problems(mount, commands_only)
+                .into_iter()
+                .for_each(&mut report);
+        }
+
+        if let Ok(canonical) = fs::canonicalize(&entry.dir) {
+            if let Some(&first) = seen_canonical.get(&canonical) {
+                report(Problem::SameDirectory(first, canonical));
+            } else {
+                seen_canonical.insert(canonical, index);
+            }
+        }
+    }
+
+    findings
+}
+
+/// Check that the entry is a readable directory and, for PATH, that it has
+/// something to run.
+fn check_dir(entry: &PathEntry, commands_only: bool) -> Option<Problem> {
+    match fs::metadata(&entry.dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Some(Problem::Missing),
+        Err(err) => return Some(Problem::Unreadable(err)),
+        Ok(meta) if !meta.is_dir() => return Some(Problem::NotADirectory),
+        Ok(_) => {}
+    }
+    let files = match fs::read_dir(&entry.dir) {
+        Ok(files) => files,
+        Err(err) => return Some(Problem::Unreadable(err)),
+    };
+    if commands_only
+        && !files
+            .filter_map(Result::ok)
+            .any(|file| search::is_command(&file.path()))
+    {
+        return Some(Problem::NoExecutables);
+    }
+    None
+}
+
+/// Problems with the mount a directory lives on. `noexec` only matters for
+/// PATH.
+fn mount_problems(mount: &Mount, commands_only: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if commands_only && mount.is_noexec() {
+        problems.push(Problem::NoExecMount(mount.mount_point.clone()));
+    }
+    if mount.is_network() {
+        problems.push(Problem::NetworkMount(
+            mount.fs_type.clone(),
+            mount.mount_point.clone(),
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_entry::Context;
+    use crate::test_util::TempDir;
+    use std::ffi::OsStr;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn context(dir: &TempDir) -> Context {
+        Context {
+            cwd: Some(dir.path().to_path_buf()),
+            home: None,
+            expand_tilde: true,
+        }
+    }
+
+    fn executable(dir: &TempDir, name: &str) {
+        let file = dir.touch(name);
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Findings as `(index, severity, message)`.
+    fn lint(dir: &TempDir, list: &str) -> Vec<(usize, Severity, String)> {
+        let entries = context(dir).parse(OsStr::new(list));
+        check(&entries, true)
+            .into_iter()
+            .map(|f| (f.index, f.problem.severity(), f.problem.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn healthy_path_has_no_findings() {
+        let dir = TempDir::new("doctor-healthy");
+        executable(&dir, "tool");
+        let list = dir.path().display().to_string();
+        assert!(lint(&dir, &list).is_empty());
+    }
+
+    #[test]
+    fn missing_and_non_directory_entries() {
+        let dir = TempDir::new("doctor-missing");
+        let file = dir.touch("file");
+        let list = format!("{}/nope:{}", dir.path().display(), file.display());
+        assert_eq!(
+            lint(&dir, &list),
+            [
+                (0, Severity::Warning, "does not exist".to_string()),
+                (1, Severity::Error, "not a directory".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn directory_without_executables() {
+        let dir = TempDir::new("doctor-noexec");
+        dir.touch("data.txt");
+        let list = dir.path().display().to_string();
+        assert_eq!(
+            lint(&dir, &list),
+            [(
+                0,
+                Severity::Warning,
+                "contains nothing executable".to_string()
+            )]
+        );
+
+        // only PATH entries need executables
+        let entries = context(&dir).parse(OsStr::new(&list));
+        assert!(check(&entries, false).is_empty());
+    }
+
+    #[test]
+    fn unreadable_directory() {
+        let dir = TempDir::new("doctor-unreadable");
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // root can read anything, so there is nothing to check
+        let readable = fs::read_dir(&locked).is_ok();
+        let findings = lint(&dir, &locked.display().to_string());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if !readable {
+            assert_eq!(findings.len(), 1);
+            assert_eq!(findings[0].1, Severity::Error);
+            assert!(findings[0].2.starts_with("cannot be read: "));
+        }
+    }
+
+    #[test]
+    fn duplicates_and_trailing_slashes() {
+        let dir = TempDir::new("doctor-duplicates");
+        executable(&dir, "tool");
+        let path = dir.path().display().to_string();
+        let list = format!("{0}:{0}/:{0}", path);
+        assert_eq!(
+            lint(&dir, &list),
+            [
+                (1, Severity::Note, "trailing slash".to_string()),
+                (1, Severity::Warning, "duplicate of [0]".to_string()),
+                (2, Severity::Warning, "duplicate of [0]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_of_the_same_directory() {
+        let dir = TempDir::new("doctor-aliases");
+        let real = dir.path().join("real");
+        fs::create_dir(&real).unwrap();
+        let real = fs::canonicalize(&real).unwrap();
+        executable(&dir, "real/tool");
+        std::os::unix::fs::symlink(&real, dir.path().join("alias")).unwrap();
+        let list = format!("{}:{}/alias", real.display(), dir.path().display());
+        assert_eq!(
+            lint(&dir, &list),
+            [(
+                1,
+                Severity::Note,
+                format!("same directory as [0] ({})", real.display())
+            )]
+        );
+    }
+
+    #[test]
+    fn entries_relative_to_the_current_directory() {
+        let dir = TempDir::new("doctor-relative");
+        executable(&dir, "tool");
+        let findings = lint(&dir, ":.:~/bin");
+        assert_eq!(
+            findings[0],
+            (
+                0,
+                Severity::Error,
+                "empty entry means the current directory".to_string()
+            )
+        );
+        assert_eq!(
+            findings[1],
+            (
+                1,
+                Severity::Error,
+                "relative entry depends on the current directory".to_string()
+            )
+        );
+        assert_eq!(
+            findings[2],
+            (1, Severity::Warning, "duplicate of [0]".to_string())
+        );
+        // without $HOME, `~/bin` is relative as well
+        assert_eq!(
+            findings[3].2,
+            "relative entry depends on the current directory"
+        );
+    }
+
+    #[test]
+    fn noexec_and_network_mounts() {
+        let mount = Mount {
+            mount_point: PathBuf::from("/net/tools"),
+            options: vec!["rw".to_string(), "noexec".to_string()],
+            fs_type: "nfs4".to_string(),
+        };
+        let problems: Vec<(Severity, String)> = mount_problems(&mount, true)
+            .into_iter()
+            .map(|problem| (problem.severity(), problem.to_string()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (
+                    Severity::Warning,
+                    "on a noexec mount (/net/tools); nothing in it can run".to_string()
+                ),
+                (
+                    Severity::Note,
+                    "on a network filesystem (nfs4 at /net/tools); lookups may be slow".to_string()
+                ),
+            ]
+        );
+        // only PATH entries need to run anything
+        assert_eq!(mount_problems(&mount, false).len(), 1);
+    }
+}
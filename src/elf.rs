@@ -0,0 +1,452 @@
+//! Reading the dynamic linking information of ELF files.
+//!
+//! Only the headers, the dynamic section and, on request, the dynamic symbol
+//! table are read, with positioned reads, so large binaries are cheap to
+//! inspect and nothing is ever executed.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+const SHT_DYNSYM: u32 = 11;
+/// Section index of undefined symbols
+const SHN_UNDEF: u16 = 0;
+
+/// Upper bound for tables read from a file, so that a corrupt header cannot
+/// make us allocate gigabytes.
+const MAX_TABLE_SIZE: u64 = 16 << 20;
+
+/// What an ELF file was built for. Libraries are only loaded into programs
+/// of the same architecture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Arch {
+    pub class64: bool,
+    pub little_endian: bool,
+    /// `e_machine`, e.g. 62 for x86-64
+    pub machine: u16,
+}
+
+/// Dynamic linking information of an ELF file.
+#[derive(Debug, PartialEq)]
+pub struct Elf {
+    pub arch: Arch,
+    /// Program interpreter (`PT_INTERP`), i.e. the dynamic linker
+    pub interpreter: Option<PathBuf>,
+    /// Libraries the file needs (`DT_NEEDED`), in order
+    pub needed: Vec<String>,
+    /// `DT_RPATH`, colon-separated
+    pub rpath: Option<String>,
+    /// `DT_RUNPATH`, colon-separated
+    pub runpath: Option<String>,
+}
+
+impl Elf {
+    /// Read the ELF file at `path`, or `None` if it is not one.
+    ///
+    /// Malformed files are errors, with kind `InvalidData`.
+    pub fn read(path: &Path) -> io::Result<Option<Elf>> {
+        match Reader::open(path)? {
+            Some(reader) => reader.parse().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Names of the dynamic symbols the ELF file at `path` imports from
+    /// its libraries, or `None` if it is not an ELF file. Files without
+    /// section headers import nothing.
+    pub fn read_imports(path: &Path) -> io::Result<Option<Vec<String>>> {
+        match Reader::open(path)? {
+            Some(reader) => reader.imports().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A program header, in either class.
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+/// Positioned reads of an ELF file of a known class and byte order.
+struct Reader {
+    file: File,
+    arch: Arch,
+}
+
+impl Reader {
+    /// Open `path` and read its ELF identification, or `None` if it is not
+    /// an ELF file.
+    fn open(path: &Path) -> io::Result<Option<Reader>> {
+        let file = File::open(path)?;
+        let mut ident = [0; 16];
+        if file.read_exact_at(&mut ident, 0).is_err() || ident[..4] != *b"\x7fELF" {
+            return Ok(None);
+        }
+        let mut reader = Reader {
+            file,
+            arch: Arch {
+                class64: match ident[4] {
+                    1 => false,
+                    2 => true,
+                    _ => return Err(invalid("unknown ELF class")),
+                },
+                little_endian: match ident[5] {
+                    1 => true,
+                    2 => false,
+                    _ => return Err(invalid("unknown ELF byte order")),
+                },
+                machine: 0,
+            },
+        };
+        let header = reader.header()?;
+        reader.arch.machine = reader.u16(&header, 18);
+        Ok(Some(reader))
+    }
+
+    fn header(&self) -> io::Result<Vec<u8>> {
+        self.bytes(0, if self.arch.class64 { 64 } else { 52 })
+    }
+
+    fn bytes(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        if len > MAX_TABLE_SIZE {
+            return Err(invalid("table too large"));
+        }
+        let mut buf = vec![0; len as usize];
+        self.file.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
+    }
+
+    fn u16(&self, buf: &[u8], at: usize) -> u16 {
+        let bytes = [buf[at], buf[at + 1]];
+        match self.arch.little_endian {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(&self, buf: &[u8], at: usize) -> u32 {
+        let bytes = buf[at..at + 4].try_into().unwrap();
+        match self.arch.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u64(&self, buf: &[u8], at: usize) -> u64 {
+        let bytes = buf[at..at + 8].try_into().unwrap();
+        match self.arch.little_endian {
+            true => u64::from_le_bytes(bytes),
+            false => u64::from_be_bytes(bytes),
+        }
+    }
+
+    /// Address-sized field: 8 bytes in 64-bit files, 4 in 32-bit ones.
+    fn word(&self, buf: &[u8], at: usize) -> u64 {
+        match self.arch.class64 {
+            true => self.u64(buf, at),
+            false => u64::from(self.u32(buf, at)),
+        }
+    }
+
+    fn parse(self) -> io::Result<Elf> {
+        let header = self.header()?;
+        let segments = self.segments(&header)?;
+
+        let interpreter = match segments.iter().find(|s| s.kind == PT_INTERP) {
+            Some(segment) => {
+                let bytes = self.bytes(segment.offset, segment.filesz)?;
+                Some(PathBuf::from(c_string(&bytes, 0)))
+            }
+            None => None,
+        };
+        let mut elf = Elf {
+            arch: self.arch,
+            interpreter,
+            needed: Vec::new(),
+            rpath: None,
+            runpath: None,
+        };
+        let Some(dynamic) = segments.iter().find(|s| s.kind == PT_DYNAMIC) else {
+            // statically linked
+            return Ok(elf);
+        };
+
+        let entries = self.dynamic_entries(dynamic)?;
+        let value = |tag| entries.iter().find(|(t, _)| *t == tag).map(|&(_, v)| v);
+        let (Some(strtab), Some(strsz)) = (value(DT_STRTAB), value(DT_STRSZ)) else {
+            return Ok(elf);
+        };
+        let strtab_offset = file_offset(&segments, strtab)
+            .ok_or_else(|| invalid("string table outside of the file"))?;
+        let strings = self.bytes(strtab_offset, strsz)?;
+        let string = |offset: u64| c_string(&strings, offset as usize);
+
+        for &(tag, value) in &entries {
+            match tag {
+                DT_NEEDED => elf.needed.push(string(value)),
+                DT_RPATH => elf.rpath = Some(string(value)),
+                DT_RUNPATH => elf.runpath = Some(string(value)),
+                _ => {}
+            }
+        }
+        Ok(elf)
+    }
+
+    fn segments(&self, header: &[u8]) -> io::Result<Vec<Segment>> {
+        let (phoff, phentsize, phnum) = match self.arch.class64 {
+            true => (
+                self.u64(header, 32),
+                self.u16(header, 54),
+                self.u16(header, 56),
+            ),
+            false => (
+                u64::from(self.u32(header, 28)),
+                self.u16(header, 42),
+                self.u16(header, 44),
+            ),
+        };
+        let min_entsize = if self.arch.class64 { 56 } else { 32 };
+        if phnum > 0 && usize::from(phentsize) < min_entsize {
+            return Err(invalid("program headers too small"));
+        }
+        let table = self.bytes(phoff, u64::from(phentsize) * u64::from(phnum))?;
+        Ok(table
+            .chunks_exact(usize::from(phentsize.max(1)))
+            .take(usize::from(phnum))
+            .map(|ph| match self.arch.class64 {
+                true => Segment {
+                    kind: self.u32(ph, 0),
+                    offset: self.u64(ph, 8),
+                    vaddr: self.u64(ph, 16),
+                    filesz: self.u64(ph, 32),
+                },
+                false => Segment {
+                    kind: self.u32(ph, 0),
+                    offset: u64::from(self.u32(ph, 4)),
+                    vaddr: u64::from(self.u32(ph, 8)),
+                    filesz: u64::from(self.u32(ph, 16)),
+                },
+            })
+            .collect())
+    }
+
+    /// Undefined symbols of the `SHT_DYNSYM` section, named through the
+    /// string table section it links to.
+    fn imports(&self) -> io::Result<Vec<String>> {
+        let header = self.header()?;
+        let (shoff, shentsize, shnum) = match self.arch.class64 {
+            true => (
+                self.u64(&header, 40),
+                self.u16(&header, 58),
+                self.u16(&header, 60),
+            ),
+            false => (
+                u64::from(self.u32(&header, 32)),
+                self.u16(&header, 46),
+                self.u16(&header, 48),
+            ),
+        };
+        let min_entsize = if self.arch.class64 { 64 } else { 40 };
+        if shnum == 0 || usize::from(shentsize) < min_entsize {
+            return Ok(Vec::new());
+        }
+        let table = self.bytes(shoff, u64::from(shentsize) * u64::from(shnum))?;
+        // (type, offset, size, link, entsize) of each section
+        let sections: Vec<(u32, u64, u64, u32, u64)> = table
+            .chunks_exact(usize::from(shentsize))
+            .map(|sh| match self.arch.class64 {
+                true => (
+                    self.u32(sh, 4),
+                    self.u64(sh, 24),
+                    self.u64(sh, 32),
+                    self.u32(sh, 40),
+                    self.u64(sh, 56),
+                ),
+                false => (
+                    self.u32(sh, 4),
+                    u64::from(self.u32(sh, 16)),
+                    u64::from(self.u32(sh, 20)),
+                    self.u32(sh, 24),
+                    u64::from(self.u32(sh, 36)),
+                ),
+            })
+            .collect();
+        let Some(&(_, offset, size, link, entsize)) =
+            sections.iter().find(|(kind, ..)| *kind == SHT_DYNSYM)
+        else {
+            return Ok(Vec::new());
+        };
+        let &(_, strings_offset, strings_size, ..) = sections
+            .get(link as usize)
+            .ok_or_else(|| invalid("symbol table without string table"))?;
+        let min_symsize = if self.arch.class64 { 24 } else { 16 };
+        if entsize < min_symsize {
+            return Err(invalid("symbols too small"));
+        }
+
+        let symbols = self.bytes(offset, size)?;
+        let strings = self.bytes(strings_offset, strings_size)?;
+        Ok(symbols
+            .chunks_exact(entsize as usize)
+            .filter_map(|sym| {
+                let (name, shndx) = match self.arch.class64 {
+                    true => (self.u32(sym, 0), self.u16(sym, 6)),
+                    false => (self.u32(sym, 0), self.u16(sym, 14)),
+                };
+                (name != 0 && shndx == SHN_UNDEF).then(|| c_string(&strings, name as usize))
+            })
+            .collect())
+    }
+
+    /// `(tag, value)` pairs of the dynamic section, up to `DT_NULL`.
+    fn dynamic_entries(&self, dynamic: &Segment) -> io::Result<Vec<(u64, u64)>> {
+        let entsize = if self.arch.class64 { 16 } else { 8 };
+        let table = self.bytes(dynamic.offset, dynamic.filesz)?;
+        Ok(table
+            .chunks_exact(entsize)
+            .map(|entry| (self.word(entry, 0), self.word(entry, entsize / 2)))
+            .take_while(|&(tag, _)| tag != DT_NULL)
+            .collect())
+    }
+}
+
+/// File offset of the virtual address `vaddr`, found through the loadable
+/// segment that maps it; `None` if no segment does, or the segment's
+/// offset is so large the file offset overflows.
+fn file_offset(segments: &[Segment], vaddr: u64) -> Option<u64> {
+    segments
+        .iter()
+        .filter(|s| s.kind == PT_LOAD)
+        .find(|s| vaddr >= s.vaddr && vaddr - s.vaddr < s.filesz)
+        .and_then(|s| (vaddr - s.vaddr).checked_add(s.offset))
+}
+
+/// NUL-terminated string at `offset`; empty if out of bounds.
+fn c_string(bytes: &[u8], offset: usize) -> String {
+    let bytes = bytes.get(offset..).unwrap_or_default();
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ElfSpec, TempDir};
+    use std::fs;
+
+    #[test]
+    fn reads_dynamic_linking_information() {
+        let dir = TempDir::new("elf-dynamic");
+        let file = dir.path().join("tool");
+        let spec = ElfSpec {
+            interpreter: Some("/lib64/ld-linux-x86-64.so.2"),
+            needed: &["libssl.so.3", "libc.so.6"],
+            runpath: Some("$ORIGIN/../lib"),
+            ..ElfSpec::default()
+        };
+        fs::write(&file, spec.build()).unwrap();
+
+        let elf = Elf::read(&file).unwrap().unwrap();
+        assert_eq!(
+            elf,
+            Elf {
+                arch: Arch {
+                    class64: true,
+                    little_endian: true,
+                    machine: 62,
+                },
+                interpreter: Some(PathBuf::from("/lib64/ld-linux-x86-64.so.2")),
+                needed: vec!["libssl.so.3".to_string(), "libc.so.6".to_string()],
+                rpath: None,
+                runpath: Some("$ORIGIN/../lib".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_imported_symbols() {
+        let dir = TempDir::new("elf-imports");
+        let file = dir.path().join("tool");
+        let spec = ElfSpec {
+            needed: &["libssl.so.3"],
+            imports: &["SSL_read", "SSL_write"],
+            exports: &["main"],
+            ..ElfSpec::default()
+        };
+        fs::write(&file, spec.build()).unwrap();
+        assert_eq!(
+            Elf::read_imports(&file).unwrap().unwrap(),
+            ["SSL_read", "SSL_write"]
+        );
+
+        let script = dir.path().join("script");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        assert_eq!(Elf::read_imports(&script).unwrap(), None);
+    }
+
+    #[test]
+    fn static_executable_needs_nothing() {
+        let dir = TempDir::new("elf-static");
+        let file = dir.path().join("tool");
+        let spec = ElfSpec {
+            dynamic: false,
+            ..ElfSpec::default()
+        };
+        fs::write(&file, spec.build()).unwrap();
+        let elf = Elf::read(&file).unwrap().unwrap();
+        assert_eq!(elf.interpreter, None);
+        assert!(elf.needed.is_empty());
+    }
+
+    #[test]
+    fn non_elf_and_malformed_files() {
+        let dir = TempDir::new("elf-malformed");
+        let script = dir.path().join("script");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        assert_eq!(Elf::read(&script).unwrap(), None);
+        let empty = dir.touch("empty");
+        assert_eq!(Elf::read(&empty).unwrap(), None);
+
+        let truncated = dir.path().join("truncated");
+        let elf = ElfSpec::default().build();
+        fs::write(&truncated, &elf[..100]).unwrap();
+        assert!(Elf::read(&truncated).is_err());
+
+        // p_offset of the loadable segment, past which the string table's
+        // file offset overflows
+        let mut elf = elf;
+        elf[72..80].copy_from_slice(&u64::MAX.to_le_bytes());
+        let overflowing = dir.path().join("overflowing");
+        fs::write(&overflowing, &elf).unwrap();
+        assert!(Elf::read(&overflowing).is_err());
+    }
+
+    #[test]
+    fn reads_system_shell() {
+        // whatever /bin/sh is linked against, it parses
+        if let Ok(Some(elf)) = Elf::read(Path::new("/bin/sh")) {
+            assert!(elf.interpreter.is_some() || elf.needed.is_empty());
+            let imports = Elf::read_imports(Path::new("/bin/sh")).unwrap().unwrap();
+            assert_eq!(imports.is_empty(), elf.needed.is_empty());
+        }
+    }
+}
@@ -197,7 +197,7 @@ mod tests {
     #[test]
     fn regex_filter_returns_error_when_invalid_pattern() {
         let filter = RegexFilter::new(r"(").unwrap_err();
-        assert_eq!(filter.to_string().contains("regex parse error"), true);
+        assert!(filter.to_string().contains("regex parse error"));
     }
 
     #[test]
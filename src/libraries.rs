@@ -0,0 +1,582 @@
+//! Finding the shared libraries of ELF executables, the way the dynamic
+//! linker would, without running it.
+//!
+//! `ldd` runs the program's own dynamic linker, which is unsafe on untrusted
+//! binaries. This follows the search order of ld.so(8) instead: `DT_RPATH`,
+//! `LD_LIBRARY_PATH`, `DT_RUNPATH`, `/etc/ld.so.cache` (or the directories
+//! of `/etc/ld.so.conf` if there is no cache) and the default directories.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::elf::{Arch, Elf};
+
+const CACHE_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+const OLD_CACHE_MAGIC: &[u8] = b"ld.so-1.7.0";
+const CACHE_HEADER_SIZE: usize = 48;
+const CACHE_ENTRY_SIZE: usize = 24;
+const OLD_CACHE_ENTRY_SIZE: usize = 12;
+
+/// Something an executable needs at load time that cannot be found.
+#[derive(Debug, PartialEq)]
+pub enum Missing {
+    Interpreter(PathBuf),
+    /// A library, needed by the executable itself or, if `needed_by` is
+    /// set, by one of its libraries
+    Library {
+        name: String,
+        needed_by: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for Missing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Missing::Interpreter(path) => {
+                write!(f, "interpreter {} does not exist", path.display())
+            }
+            Missing::Library {
+                name,
+                needed_by: None,
+            } => write!(f, "{} not found", name),
+            Missing::Library {
+                name,
+                needed_by: Some(library),
+            } => write!(f, "{} not found (needed by {})", name, library.display()),
+        }
+    }
+}
+
+/// Library search configuration, plus what has been looked up so far.
+pub struct Linker {
+    /// `(name, path)` entries of `/etc/ld.so.cache`, in cache order
+    cache: Vec<(String, PathBuf)>,
+    /// Directories of `/etc/ld.so.conf`, searched when there is no cache
+    conf_dirs: Vec<PathBuf>,
+    library_path: Vec<PathBuf>,
+    /// Libraries parsed so far; `None` for files that are not usable ELF
+    libraries: RefCell<HashMap<PathBuf, Option<Rc<Elf>>>>,
+    /// Results of the search after `DT_RUNPATH`, which is the same for
+    /// every executable
+    system_lookups: RefCell<HashMap<(String, Arch), Option<PathBuf>>>,
+}
+
+impl Linker {
+    /// Configuration of this system and of our `LD_LIBRARY_PATH`.
+    pub fn load() -> Self {
+        let cache = fs::read("/etc/ld.so.cache")
+            .ok()
+            .and_then(|cache| parse_cache(&cache));
+        let conf_dirs = match cache {
+            Some(_) => Vec::new(),
+            None => read_conf(Path::new("/etc/ld.so.conf"), 0),
+        };
+        let library_path = env::var_os("LD_LIBRARY_PATH")
+            .map(|list| env::split_paths(&list).collect())
+            .unwrap_or_default();
+        Linker::new(cache.unwrap_or_default(), conf_dirs, library_path)
+    }
+
+    fn new(
+        cache: Vec<(String, PathBuf)>,
+        conf_dirs: Vec<PathBuf>,
+        library_path: Vec<PathBuf>,
+    ) -> Self {
+        Linker {
+            cache,
+            conf_dirs,
+            library_path,
+            libraries: RefCell::new(HashMap::new()),
+            system_lookups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// What the executable at `path` needs but cannot get: its interpreter,
+    /// and libraries it or its libraries need. Files that are not dynamically
+    /// linked ELF files need nothing.
+    pub fn missing(&self, path: &Path) -> Vec<Missing> {
+        let Ok(Some(exe)) = Elf::read(path) else {
+            return Vec::new();
+        };
+        let mut missing = Vec::new();
+        if let Some(interpreter) = &exe.interpreter
+            && fs::metadata(interpreter).is_err()
+        {
+            missing.push(Missing::Interpreter(interpreter.clone()));
+        }
+
+        let exe = Rc::new(exe);
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([(Rc::clone(&exe), path.to_path_buf())]);
+        while let Some((object, object_path)) = queue.pop_front() {
+            for name in &object.needed {
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+                match self.find(name, (&object, &object_path), (&exe, path)) {
+                    Some((library, library_path)) => queue.push_back((library, library_path)),
+                    None => missing.push(Missing::Library {
+                        name: name.clone(),
+                        needed_by: (!Rc::ptr_eq(&object, &exe)).then(|| object_path.clone()),
+                    }),
+                }
+            }
+        }
+        missing
+    }
+
+    /// Find library `name` needed by `object`, loaded into `exe`.
+    fn find(
+        &self,
+        name: &str,
+        (object, object_path): (&Elf, &Path),
+        (exe, exe_path): (&Elf, &Path),
+    ) -> Option<(Rc<Elf>, PathBuf)> {
+        let arch = exe.arch;
+        if name.contains('/') {
+            let path = PathBuf::from(name);
+            return self.library(&path, arch).map(|library| (library, path));
+        }
+
+        // DT_RPATH is ignored when the object also has DT_RUNPATH, and is
+        // inherited from the executable
+        let mut dirs = Vec::new();
+        if object.runpath.is_none() {
+            dirs.extend(search_dirs(object.rpath.as_deref(), object_path, arch));
+            if exe.runpath.is_none() && !std::ptr::eq(object, exe) {
+                dirs.extend(search_dirs(exe.rpath.as_deref(), exe_path, arch));
+            }
+        }
+        dirs.extend(self.library_path.iter().cloned());
+        dirs.extend(search_dirs(object.runpath.as_deref(), object_path, arch));
+        if let Some(found) = self.find_in(name, &dirs, arch) {
+            return Some(found);
+        }
+
+        let key = (name.to_string(), arch);
+        if let Some(path) = self.system_lookups.borrow().get(&key) {
+            let path = path.clone()?;
+            return self.library(&path, arch).map(|library| (library, path));
+        }
+        let found = self.find_in_system(name, arch);
+        self.system_lookups
+            .borrow_mut()
+            .insert(key, found.as_ref().map(|(_, path)| path.clone()));
+        found
+    }
+
+    /// Find a library in the cache (or `ld.so.conf`), then in the default
+    /// directories.
+    fn find_in_system(&self, name: &str, arch: Arch) -> Option<(Rc<Elf>, PathBuf)> {
+        for (cached_name, path) in &self.cache {
+            if cached_name == name
+                && let Some(library) = self.library(path, arch)
+            {
+                return Some((library, path.clone()));
+            }
+        }
+        self.find_in(name, &self.conf_dirs, arch)
+            .or_else(|| self.find_in(name, &default_dirs(arch), arch))
+    }
+
+    fn find_in(&self, name: &str, dirs: &[PathBuf], arch: Arch) -> Option<(Rc<Elf>, PathBuf)> {
+        dirs.iter().find_map(|dir| {
+            let path = dir.join(name);
+            self.library(&path, arch).map(|library| (library, path))
+        })
+    }
+
+    /// The library at `path`, if it is an ELF file for `arch`. The dynamic
+    /// linker skips files built for another architecture.
+    fn library(&self, path: &Path, arch: Arch) -> Option<Rc<Elf>> {
+        let parsed = self.libraries.borrow().get(path).cloned();
+        let library = match parsed {
+            Some(library) => library,
+            None => {
+                let library = Elf::read(path).ok().flatten().map(Rc::new);
+                self.libraries
+                    .borrow_mut()
+                    .insert(path.to_path_buf(), library.clone());
+                library
+            }
+        };
+        library.filter(|library| library.arch == arch)
+    }
+}
+
+/// Directories of a `DT_RPATH` or `DT_RUNPATH` value, with `$ORIGIN` (the
+/// directory of the object) and `$LIB` expanded.
+fn search_dirs(list: Option<&str>, object_path: &Path, arch: Arch) -> Vec<PathBuf> {
+    let Some(list) = list else {
+        return Vec::new();
+    };
+    let origin = fs::canonicalize(object_path)
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let origin = origin.to_string_lossy();
+    let lib = if arch.class64 { "lib64" } else { "lib" };
+    list.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| {
+            let dir = dir
+                .replace("${ORIGIN}", &origin)
+                .replace("$ORIGIN", &origin)
+                .replace("${LIB}", lib)
+                .replace("$LIB", lib);
+            PathBuf::from(dir)
+        })
+        .collect()
+}
+
+/// Directories the dynamic linker always searches last.
+fn default_dirs(arch: Arch) -> Vec<PathBuf> {
+    let dirs: &[&str] = match arch.class64 {
+        true => &["/lib64", "/usr/lib64", "/lib", "/usr/lib"],
+        false => &["/lib", "/usr/lib"],
+    };
+    dirs.iter().map(PathBuf::from).collect()
+}
+
+/// Parse `/etc/ld.so.cache` into `(name, path)` entries. Caches in the
+/// old format are followed by one in the new format, which is used.
+fn parse_cache(cache: &[u8]) -> Option<Vec<(String, PathBuf)>> {
+    let u32_at = |bytes: &[u8], at: usize| -> Option<u32> {
+        Some(u32::from_ne_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+    };
+
+    let mut cache = cache;
+    if cache.starts_with(OLD_CACHE_MAGIC) {
+        let nlibs = u32_at(cache, 12)? as usize;
+        let old_size = 16 + nlibs * OLD_CACHE_ENTRY_SIZE;
+        cache = cache.get(old_size.next_multiple_of(8)..)?;
+    }
+    if !cache.starts_with(CACHE_MAGIC) {
+        return None;
+    }
+
+    // string offsets are relative to the start of the new-format cache
+    let string = |offset: u32| -> Option<&[u8]> {
+        let bytes = cache.get(offset as usize..)?;
+        Some(&bytes[..bytes.iter().position(|&b| b == 0)?])
+    };
+    let nlibs = u32_at(cache, 20)? as usize;
+    (0..nlibs)
+        .map(|i| {
+            let entry = CACHE_HEADER_SIZE + i * CACHE_ENTRY_SIZE;
+            let name = string(u32_at(cache, entry + 4)?)?;
+            let path = string(u32_at(cache, entry + 8)?)?;
+            Some((
+                String::from_utf8_lossy(name).into_owned(),
+                PathBuf::from(OsStr::from_bytes(path)),
+            ))
+        })
+        .collect()
+}
+
+/// Directories listed in an `ld.so.conf` file, following `include` lines.
+fn read_conf(path: &Path, depth: usize) -> Vec<PathBuf> {
+    // includes can loop
+    if depth > 8 {
+        return Vec::new();
+    }
+    let Ok(conf) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let base = path.parent().unwrap_or(Path::new("/"));
+    let mut dirs = Vec::new();
+    for line in conf.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(pattern) = line.strip_prefix("include") {
+            for pattern in pattern.split_whitespace() {
+                for file in glob(&base.join(pattern)) {
+                    dirs.extend(read_conf(&file, depth + 1));
+                }
+            }
+        } else if !line.is_empty() && !line.starts_with("hwcap") {
+            dirs.extend(
+                line.split([' ', '\t', ',', ':'])
+                    .filter(|dir| !dir.is_empty())
+                    .map(PathBuf::from),
+            );
+        }
+    }
+    dirs
+}
+
+/// Files matching `pattern`, which may have wildcards (`*` and `?`) in its
+/// last component only, in sorted order.
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy();
+    if !name.contains(['*', '?']) {
+        return vec![pattern.to_path_buf()];
+    }
+    let Ok(files) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = files
+        .filter_map(Result::ok)
+        .filter(|file| wildcard_match(name.as_bytes(), file.file_name().as_encoded_bytes()))
+        .map(|file| file.path())
+        .collect();
+    matches.sort();
+    matches
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_match(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && wildcard_match(rest, name_rest),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ElfSpec, TempDir};
+
+    /// Cache in the new format with the given entries.
+    fn cache_file(entries: &[(&str, &str)]) -> Vec<u8> {
+        let strings_start = CACHE_HEADER_SIZE + entries.len() * CACHE_ENTRY_SIZE;
+        let mut header = CACHE_MAGIC.to_vec();
+        header.extend((entries.len() as u32).to_ne_bytes());
+        header.resize(CACHE_HEADER_SIZE, 0);
+        let mut strings = Vec::new();
+        let mut table = Vec::new();
+        for (name, path) in entries {
+            let name_offset = (strings_start + strings.len()) as u32;
+            strings.extend(name.as_bytes());
+            strings.push(0);
+            let path_offset = (strings_start + strings.len()) as u32;
+            strings.extend(path.as_bytes());
+            strings.push(0);
+            table.extend(0x0303i32.to_ne_bytes());
+            table.extend(name_offset.to_ne_bytes());
+            table.extend(path_offset.to_ne_bytes());
+            table.extend([0; 12]);
+        }
+        [header, table, strings].concat()
+    }
+
+    #[test]
+    fn parse_new_cache() {
+        let cache = cache_file(&[
+            ("libc.so.6", "/lib/x86_64-linux-gnu/libc.so.6"),
+            ("libm.so.6", "/lib/x86_64-linux-gnu/libm.so.6"),
+        ]);
+        assert_eq!(
+            parse_cache(&cache).unwrap(),
+            [
+                (
+                    "libc.so.6".to_string(),
+                    PathBuf::from("/lib/x86_64-linux-gnu/libc.so.6")
+                ),
+                (
+                    "libm.so.6".to_string(),
+                    PathBuf::from("/lib/x86_64-linux-gnu/libm.so.6")
+                ),
+            ]
+        );
+        assert_eq!(parse_cache(b"not a cache"), None);
+        assert_eq!(parse_cache(&cache[..60]), None);
+    }
+
+    #[test]
+    fn parse_cache_after_old_format() {
+        let mut cache = OLD_CACHE_MAGIC.to_vec();
+        cache.push(0);
+        cache.extend(1u32.to_ne_bytes());
+        cache.extend([0; OLD_CACHE_ENTRY_SIZE]);
+        // padding to 8 bytes
+        cache.extend([0; 4]);
+        cache.extend(cache_file(&[("libz.so.1", "/usr/lib/libz.so.1")]));
+        assert_eq!(parse_cache(&cache).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_system_cache() {
+        if let Ok(cache) = fs::read("/etc/ld.so.cache") {
+            let entries = parse_cache(&cache).unwrap();
+            assert!(entries.iter().all(|(_, path)| path.is_absolute()));
+        }
+    }
+
+    #[test]
+    fn conf_follows_includes() {
+        let dir = TempDir::new("libraries-conf");
+        let conf_d = dir.path().join("ld.so.conf.d");
+        fs::create_dir(&conf_d).unwrap();
+        fs::write(conf_d.join("b.conf"), "/opt/b/lib\n").unwrap();
+        fs::write(
+            conf_d.join("a.conf"),
+            "# comment\n/opt/a/lib /opt/a/lib64\n",
+        )
+        .unwrap();
+        fs::write(conf_d.join("ignored.txt"), "/opt/ignored\n").unwrap();
+        let conf = dir.path().join("ld.so.conf");
+        fs::write(&conf, "include ld.so.conf.d/*.conf\n/usr/local/lib\n").unwrap();
+        assert_eq!(
+            read_conf(&conf, 0),
+            [
+                PathBuf::from("/opt/a/lib"),
+                PathBuf::from("/opt/a/lib64"),
+                PathBuf::from("/opt/b/lib"),
+                PathBuf::from("/usr/local/lib"),
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match(b"*.conf", b"libc.conf"));
+        assert!(wildcard_match(b"lib?.conf", b"libc.conf"));
+        assert!(!wildcard_match(b"*.conf", b"libc.conf~"));
+        assert!(wildcard_match(b"*", b""));
+    }
+
+    fn write_elf(dir: &Path, name: &str, spec: ElfSpec) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, spec.build()).unwrap();
+        path
+    }
+
+    #[test]
+    fn reports_missing_libraries_and_interpreter() {
+        let dir = TempDir::new("libraries-missing");
+        let lib = dir.path().join("lib");
+        fs::create_dir(&lib).unwrap();
+        write_elf(
+            &lib,
+            "libfound.so.1",
+            ElfSpec {
+                needed: &["libdeep.so.2"],
+                ..ElfSpec::default()
+            },
+        );
+        // built for another architecture, so skipped
+        write_elf(
+            &lib,
+            "libarm.so.1",
+            ElfSpec {
+                machine: 183,
+                ..ElfSpec::default()
+            },
+        );
+        let exe = write_elf(
+            dir.path(),
+            "tool",
+            ElfSpec {
+                interpreter: Some("/nonexistent/ld.so"),
+                needed: &["libfound.so.1", "libarm.so.1", "libgone.so.3"],
+                ..ElfSpec::default()
+            },
+        );
+
+        let linker = Linker::new(Vec::new(), Vec::new(), vec![lib.clone()]);
+        assert_eq!(
+            linker.missing(&exe),
+            [
+                Missing::Interpreter(PathBuf::from("/nonexistent/ld.so")),
+                Missing::Library {
+                    name: "libarm.so.1".to_string(),
+                    needed_by: None,
+                },
+                Missing::Library {
+                    name: "libgone.so.3".to_string(),
+                    needed_by: None,
+                },
+                Missing::Library {
+                    name: "libdeep.so.2".to_string(),
+                    needed_by: Some(lib.join("libfound.so.1")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn runpath_origin_and_cache() {
+        let dir = TempDir::new("libraries-runpath");
+        let bin = dir.path().join("bin");
+        let lib = dir.path().join("lib");
+        let cached = dir.path().join("cached");
+        for sub in [&bin, &lib, &cached] {
+            fs::create_dir(sub).unwrap();
+        }
+        write_elf(&lib, "libown.so", ElfSpec::default());
+        let cached_lib = write_elf(&cached, "libcached.so", ElfSpec::default());
+        let exe = write_elf(
+            &bin,
+            "tool",
+            ElfSpec {
+                needed: &["libown.so", "libcached.so"],
+                runpath: Some("$ORIGIN/../lib"),
+                ..ElfSpec::default()
+            },
+        );
+
+        let cache = vec![("libcached.so".to_string(), cached_lib.clone())];
+        let linker = Linker::new(cache, Vec::new(), Vec::new());
+        assert_eq!(linker.missing(&exe), []);
+        // the cache lookup is remembered for other executables
+        let key = (
+            "libcached.so".to_string(),
+            Elf::read(&exe).unwrap().unwrap().arch,
+        );
+        assert_eq!(linker.system_lookups.borrow()[&key], Some(cached_lib));
+    }
+
+    #[test]
+    fn rpath_is_ignored_with_runpath() {
+        let dir = TempDir::new("libraries-rpath");
+        let lib = dir.path().join("lib");
+        fs::create_dir(&lib).unwrap();
+        write_elf(&lib, "libown.so", ElfSpec::default());
+        let rpath = lib.to_str().unwrap();
+        let with_rpath = write_elf(
+            dir.path(),
+            "rpath",
+            ElfSpec {
+                needed: &["libown.so"],
+                rpath: Some(rpath),
+                ..ElfSpec::default()
+            },
+        );
+        let with_both = write_elf(
+            dir.path(),
+            "both",
+            ElfSpec {
+                needed: &["libown.so"],
+                rpath: Some(rpath),
+                runpath: Some("/nonexistent"),
+                ..ElfSpec::default()
+            },
+        );
+        let linker = Linker::new(Vec::new(), Vec::new(), Vec::new());
+        assert_eq!(linker.missing(&with_rpath), []);
+        assert_eq!(linker.missing(&with_both).len(), 1);
+    }
+
+    #[test]
+    fn system_binaries_resolve() {
+        // a working system can load its own shell
+        let linker = Linker::load();
+        if Path::new("/bin/sh").exists() && env::var_os("LD_LIBRARY_PATH").is_none() {
+            assert_eq!(linker.missing(Path::new("/bin/sh")), []);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Capturing the PATH of a login shell.
+//!
+//! Editors and GUI launchers often see a different PATH than terminals,
+//! because shell startup files only run for login or interactive shells.
+//! This spawns the user's shell the way a terminal would and asks it for
+//! the value of the variable.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::sys;
+
+/// How long the shell gets to start up and print the variable
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Startup files may print anything (greetings, MOTD), so the value is
+// wrapped in markers.
+const BEGIN_MARKER: &str = "__PATHSEARCH_BEGIN__";
+const END_MARKER: &str = "__PATHSEARCH_END__";
+
+/// Shell to use when none is given: `$SHELL`, or `/bin/sh`.
+pub fn default_shell() -> OsString {
+    std::env::var_os("SHELL")
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| OsString::from("/bin/sh"))
+}
+
+/// Whether the argument `arg` after `--login-shell` names the shell rather
+/// than being the pattern: it is a path, or the name of a shell listed in
+/// `/etc/shells`.
+pub fn names_shell(arg: &str) -> bool {
+    let shells = fs::read_to_string("/etc/shells").unwrap_or_default();
+    is_shell(arg, &shells)
+}
+
+fn is_shell(arg: &str, shells: &str) -> bool {
+    if arg.contains('/') {
+        return true;
+    }
+    shells
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| Path::new(line.trim()).file_name())
+        .any(|name| name == arg)
+}
+
+/// Run `shell -l -i -c` and return the value of `var` in that shell.
+pub fn capture(shell: &Path, var: &str, timeout: Duration) -> Result<OsString, String> {
+    if !is_variable_name(var) {
+        return Err(format!("Invalid variable name '{}'", var));
+    }
+
+    let script = format!("printf '%s%s%s' {BEGIN_MARKER} \"${var}\" {END_MARKER}");
+    let mut child = Command::new(shell)
+        .args(["-l", "-i", "-c", &script])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // own process group, so a timeout also stops anything it started
+        .process_group(0)
+        .spawn()
+        .map_err(|err| format!("Failed to start shell '{}': {}", shell.display(), err))?;
+
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                sys::kill_process_group(child.id());
+                let _ = child.wait();
+                return Err(format!(
+                    "Shell '{}' did not finish within {}s",
+                    shell.display(),
+                    timeout.as_secs_f64()
+                ));
+            }
+            Err(err) => {
+                return Err(format!(
+                    "Failed to wait for shell '{}': {}",
+                    shell.display(),
+                    err
+                ));
+            }
+        }
+    };
+
+    // background jobs started by startup files may keep the pipes open
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+    let stdout = stdout.recv_timeout(remaining()).unwrap_or_default();
+    let stderr = stderr.recv_timeout(remaining()).unwrap_or_default();
+
+    match extract_value(&stdout) {
+        Some(value) => Ok(OsString::from_vec(value.to_vec())),
+        None => {
+            let stderr = String::from_utf8_lossy(&stderr);
+            let detail = stderr.lines().rev().find(|line| !line.trim().is_empty());
+            Err(format!(
+                "Shell '{}' did not report {} ({}){}",
+                shell.display(),
+                var,
+                status,
+                detail.map(|line| format!(": {}", line)).unwrap_or_default()
+            ))
+        }
+    }
+}
+
+/// Read a pipe to the end on another thread.
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    if let Some(mut pipe) = pipe {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = pipe.read_to_end(&mut buf);
+            let _ = sender.send(buf);
+        });
+    }
+    receiver
+}
+
+/// Extract the value between the markers from the shell's output.
+fn extract_value(output: &[u8]) -> Option<&[u8]> {
+    let begin = find(output, BEGIN_MARKER.as_bytes())? + BEGIN_MARKER.len();
+    let len = find(&output[begin..], END_MARKER.as_bytes())?;
+    Some(&output[begin..begin + len])
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn fake_shell(dir: &TempDir, script: &str) -> std::path::PathBuf {
+        let shell = dir.path().join("shell");
+        fs::write(&shell, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+        shell
+    }
+
+    #[test]
+    fn extract_value_between_markers() {
+        let output = b"Welcome!\n__PATHSEARCH_BEGIN__/usr/bin:/bin__PATHSEARCH_END__";
+        assert_eq!(extract_value(output), Some(&b"/usr/bin:/bin"[..]));
+    }
+
+    #[test]
+    fn extract_value_empty() {
+        let output = b"__PATHSEARCH_BEGIN____PATHSEARCH_END__";
+        assert_eq!(extract_value(output), Some(&b""[..]));
+    }
+
+    #[test]
+    fn extract_value_missing_markers() {
+        assert_eq!(extract_value(b"/usr/bin:/bin"), None);
+        assert_eq!(extract_value(b"__PATHSEARCH_BEGIN__/usr/bin"), None);
+    }
+
+    #[test]
+    fn variable_names() {
+        assert!(is_variable_name("PATH"));
+        assert!(is_variable_name("_X1"));
+        assert!(!is_variable_name(""));
+        assert!(!is_variable_name("1X"));
+        assert!(!is_variable_name("PATH\"; rm -rf ~; \""));
+    }
+
+    #[test]
+    fn shells_are_paths_or_listed() {
+        let shells = "# /etc/shells: valid login shells\n/bin/sh\n/usr/bin/zsh\n";
+        assert!(is_shell("zsh", shells));
+        assert!(is_shell("sh", shells));
+        assert!(is_shell("/opt/bin/fish", shells));
+        assert!(is_shell("./fish", shells));
+        assert!(!is_shell("fish", shells));
+        assert!(!is_shell("python3", shells));
+        assert!(!is_shell("shells:", shells));
+    }
+
+    #[test]
+    fn capture_from_posix_shell() {
+        let value = capture(Path::new("/bin/sh"), "PATHSEARCH_TEST_VAR", TIMEOUT);
+        assert_eq!(value, Ok(OsString::new()));
+    }
+
+    #[test]
+    fn capture_skips_startup_output() {
+        let tmp = TempDir::new("login-shell-output");
+        // ignores its arguments, like a shell with a chatty startup file
+        let shell = fake_shell(
+            &tmp,
+            "echo hello\nprintf '__PATHSEARCH_BEGIN__/opt/bin__PATHSEARCH_END__'",
+        );
+        let value = capture(&shell, "PATH", TIMEOUT);
+        assert_eq!(value, Ok(OsString::from("/opt/bin")));
+    }
+
+    #[test]
+    fn capture_times_out() {
+        let tmp = TempDir::new("login-shell-timeout");
+        let shell = fake_shell(&tmp, "sleep 5");
+        let err = capture(&shell, "PATH", Duration::from_millis(100)).unwrap_err();
+        assert!(err.contains("did not finish"), "{}", err);
+    }
+
+    #[test]
+    fn capture_reports_failure() {
+        let tmp = TempDir::new("login-shell-failure");
+        let shell = fake_shell(&tmp, "echo 'bad rc file' >&2\nexit 3");
+        let err = capture(&shell, "PATH", TIMEOUT).unwrap_err();
+        assert!(err.contains("bad rc file"), "{}", err);
+    }
+
+    #[test]
+    fn capture_reports_missing_shell() {
+        let err = capture(Path::new("/nonexistent/shell"), "PATH", TIMEOUT).unwrap_err();
+        assert!(err.starts_with("Failed to start shell"), "{}", err);
+    }
+}
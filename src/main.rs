@@ -4,19 +4,81 @@
 //! matching a given pattern. Results are displayed in PATH order, so the first
 //! match is the file that would run if you typed the command.
 
+use std::cell::OnceCell;
+use std::ffi::{OsStr, OsString};
 use std::io::{self, IsTerminal, Write};
-use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs, process};
 
+mod audit;
+mod broken;
+mod clean_path;
+mod content_filter;
+mod diff;
+mod dir_reader;
+mod doctor;
+mod elf;
 mod filename_filter;
-use filename_filter::{
-    FileNameFilter, FilterResult, MatchAllFilter, MatchRange, RegexFilter, SubstringFilter,
-};
+mod libraries;
+mod login_shell;
+mod man;
+mod mounts;
+mod output;
+mod path_entry;
+mod path_var;
+mod permissions;
+mod privileged;
+mod proc_env;
+mod search;
+mod shebang;
+mod sys;
+#[cfg(test)]
+mod test_util;
+mod users;
+use clean_path::Syntax;
+use content_filter::ContentFilter;
+use dir_reader::DirReader;
+use filename_filter::{FileNameFilter, FilterResult, MatchAllFilter, RegexFilter, SubstringFilter};
+use output::FormattedOutput;
+use path_entry::PathEntry;
+use permissions::Credentials;
+use proc_env::ProcessEnv;
+use search::DirRange;
 
 struct Args {
     pattern: Option<String>,
     regex: bool,
     color: ColorOption,
+    mode: Mode,
+    first: bool,
+    rank: bool,
+    dir_range: DirRange,
+    group: bool,
+    show_empty: bool,
+    diff_from: Option<String>,
+    diff_to: Option<String>,
+    prepend: Vec<PathBuf>,
+    append: Vec<PathBuf>,
+    path: Option<String>,
+    var: String,
+    /// `Some(None)` to use the default shell
+    login_shell: Option<Option<String>>,
+    pid: Option<u32>,
+    /// Shell syntax for `--emit-clean-path`; `None` to use that of $SHELL
+    syntax: Option<Syntax>,
+    as_user: Option<String>,
+    /// Comma-separated group names or IDs
+    groups: Option<String>,
+    dir_timeout: Option<Duration>,
+    timings: bool,
+    links_against: Option<String>,
+    imports: Option<String>,
+    interpreter: Option<String>,
+    contains: Option<String>,
+    binary: bool,
+    describe: bool,
+    search_descriptions: bool,
 }
 
 #[derive(Default, Clone, Copy)]
@@ -35,12 +97,95 @@ enum SearchType {
     Regex,
 }
 
+/// What to do with the matched files.
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Print every match in PATH order
+    #[default]
+    Search,
+    /// Print only matches that are dangling symlinks or cannot be stat'ed
+    Broken,
+    /// Print only scripts whose `#!` line is broken
+    BrokenScripts,
+    /// Print only ELF executables whose libraries cannot be found
+    MissingLibs,
+    /// Print only setuid, setgid and file-capability executables
+    Privileged,
+    /// Print only names found in more than one directory, grouped by name
+    Shadowed,
+    /// Compare command resolution between two PATH values
+    Diff,
+    /// Show how prepending or appending directories changes resolution
+    Preview,
+    /// Check the path list for problems
+    Doctor,
+    /// Print a shell command setting a cleaned-up path list
+    CleanPath,
+    /// Check the path list for ways to hijack commands
+    Audit,
+}
+
+impl Mode {
+    /// Command line flag that selects the mode.
+    fn flag(self) -> &'static str {
+        match self {
+            Mode::Search => "",
+            Mode::Broken => "--broken",
+            Mode::BrokenScripts => "--broken-scripts",
+            Mode::MissingLibs => "--missing-libs",
+            Mode::Privileged => "--privileged",
+            Mode::Shadowed => "--shadowed",
+            Mode::Diff => "diff",
+            Mode::Preview => "--prepend/--append",
+            Mode::Doctor => "doctor",
+            Mode::CleanPath => "--emit-clean-path",
+            Mode::Audit => "audit",
+        }
+    }
+}
+
 struct Config {
-    dirs: Vec<PathBuf>,
+    /// Entries of the searched path list, resolved to directories
+    entries: Vec<PathEntry>,
+    /// Name of the path-list variable being searched
+    var: String,
     pattern: Option<String>,
     search_type: SearchType,
     /// Enable color output. Default true unless not a TTY.
     color: bool,
+    mode: Mode,
+    /// Only print the entry the shell would run for each name
+    first: bool,
+    /// Prefix results with their PATH index and per-name rank
+    rank: bool,
+    /// PATH indexes to search
+    dir_range: DirRange,
+    /// Group results under a header for each directory
+    group: bool,
+    /// Print headers of directories without matches when grouping
+    show_empty: bool,
+    /// Old PATH directories to compare `entries` against in diff mode
+    diff_from: Vec<PathBuf>,
+    /// Directories to simulate prepending and appending to PATH
+    prepend: Vec<PathBuf>,
+    append: Vec<PathBuf>,
+    /// Shell syntax of the `--emit-clean-path` command
+    syntax: Syntax,
+    /// User to decide executability for, if not the caller
+    user: Option<Credentials>,
+    /// How long to wait for a directory to be listed before skipping it
+    dir_timeout: Option<Duration>,
+    /// Report how long each directory took to list
+    timings: bool,
+    /// Predicates on the contents of matches
+    content_filter: ContentFilter,
+    /// Print the man page description of each match
+    describe: bool,
+    /// Match the pattern against descriptions as well as names
+    search_descriptions: bool,
+    /// Terminal width for laying out grouped results in columns. `None`
+    /// when stdout is not a TTY.
+    columns: Option<usize>,
 }
 
 impl Config {
@@ -54,8 +199,61 @@ impl Config {
                 process::exit(1);
             }
         };
-        let path = env::var("PATH").expect("Failed to get PATH");
-        let dirs = env::split_paths(&path).collect();
+        let process = args
+            .pid
+            .map(ProcessEnv::read)
+            .transpose()
+            .unwrap_or_else(|err| exit_with_error(&err));
+        let path = if let Some(path) = args.path {
+            Ok(OsString::from(path))
+        } else if let Some(shell) = args.login_shell {
+            let shell = shell.map_or_else(login_shell::default_shell, OsString::from);
+            login_shell::capture(Path::new(&shell), &args.var, login_shell::TIMEOUT)
+                .and_then(|value| path_var::resolve_value(&args.var, Some(value)))
+        } else if let Some(process) = &process {
+            path_var::resolve_value(&args.var, process.var(&args.var))
+        } else {
+            path_var::value(&args.var)
+        };
+        let path = path.unwrap_or_else(|err| exit_with_error(&err));
+
+        // empty, relative and `~` entries are resolved in the environment
+        // of the inspected process, if any; bash only expands `~` in PATH
+        let expand_tilde = args.var == "PATH";
+        let context = match &process {
+            Some(process) => path_entry::Context {
+                cwd: Some(process.cwd().unwrap_or_else(|err| exit_with_error(&err))),
+                home: process.var("HOME").map(PathBuf::from),
+                expand_tilde,
+            },
+            None => path_entry::Context::current(expand_tilde),
+        };
+
+        // in diff mode, an omitted --from or --to defaults to the searched path
+        let entries = context.parse(args.diff_to.as_deref().map_or(&*path, OsStr::new));
+        let diff_from = context
+            .parse(args.diff_from.as_deref().map_or(&*path, OsStr::new))
+            .into_iter()
+            .map(|entry| entry.dir)
+            .collect();
+        let resolve_dirs = |dirs: Vec<PathBuf>| {
+            dirs.iter()
+                .map(|dir| context.resolve(dir.as_os_str()).dir)
+                .collect()
+        };
+
+        let mut user = args.as_user.as_ref().map(|name| {
+            let user = users::by_name(name)
+                .or_else(|| name.parse().ok().and_then(users::by_uid))
+                .unwrap_or_else(|| exit_with_error(&format!("No user named '{}'", name)));
+            Credentials::of_user(&user)
+        });
+        if let Some(groups) = &args.groups {
+            let gids =
+                permissions::parse_groups(groups).unwrap_or_else(|err| exit_with_error(&err));
+            user.get_or_insert_with(Credentials::current).gids = gids;
+        }
+
         let search_type = if args.pattern.is_none() {
             SearchType::MatchAll
         } else if args.regex {
@@ -63,6 +261,23 @@ impl Config {
         } else {
             SearchType::Substring
         };
+        // -r applies to the patterns of content predicates too
+        let pattern_filter = |pattern: String| -> Box<dyn FileNameFilter> {
+            if !args.regex {
+                return Box::new(SubstringFilter::new(&pattern));
+            }
+            Box::new(RegexFilter::new(&pattern).unwrap_or_else(|err| {
+                exit_with_error(&format!("Invalid regex pattern '{}': {}", pattern, err))
+            }))
+        };
+        let content_filter = ContentFilter {
+            links_against: args.links_against.map(pattern_filter),
+            imports: args.imports,
+            interpreter: args.interpreter.map(pattern_filter),
+            dirs: entries.iter().map(|entry| entry.dir.clone()).collect(),
+            contains: args.contains.map(pattern_filter),
+            binary: args.binary,
+        };
         let color = match args.color {
             ColorOption::Auto => io::stdout().is_terminal(),
             ColorOption::Always => true,
@@ -70,16 +285,36 @@ impl Config {
         };
 
         Config {
-            dirs,
+            entries,
+            var: args.var,
             pattern: args.pattern,
             search_type,
             color,
+            mode: args.mode,
+            first: args.first,
+            rank: args.rank,
+            dir_range: args.dir_range,
+            group: args.group,
+            show_empty: args.show_empty,
+            diff_from,
+            prepend: resolve_dirs(args.prepend),
+            append: resolve_dirs(args.append),
+            syntax: args
+                .syntax
+                .unwrap_or_else(|| Syntax::from_shell(env::var_os("SHELL").as_deref())),
+            user,
+            dir_timeout: args.dir_timeout,
+            timings: args.timings,
+            content_filter,
+            describe: args.describe || args.search_descriptions,
+            search_descriptions: args.search_descriptions,
+            columns: terminal_columns(),
         }
     }
 
     fn validate(&self) -> bool {
-        if self.dirs.is_empty() {
-            eprintln!("No directories in PATH");
+        if self.entries.is_empty() {
+            eprintln!("No directories in {}", self.var);
             return false;
         }
 
@@ -90,14 +325,72 @@ impl Config {
 // Manual argument parser (replaces clap)
 impl Args {
     fn parse_manual() -> Result<Args, String> {
-        let mut args_iter = env::args().skip(1);
+        let mut args_iter = env::args().skip(1).peekable();
         let mut pattern = None;
         let mut regex = false;
         let mut color = ColorOption::Auto;
+        let mut mode = Mode::Search;
+        let mut first = false;
+        let mut rank = false;
+        let mut dir_range = DirRange::default();
+        let mut group = false;
+        let mut show_empty = false;
+        let mut diff_from = None;
+        let mut diff_to = None;
+        let mut prepend = Vec::new();
+        let mut append = Vec::new();
+        let mut path = None;
+        let mut var = "PATH".to_string();
+        let mut login_shell = None;
+        let mut pid = None;
+        let mut syntax = None;
+        let mut as_user = None;
+        let mut groups = None;
+        let mut dir_timeout = None;
+        let mut timings = false;
+        let mut links_against = None;
+        let mut imports = None;
+        let mut interpreter = None;
+        let mut contains = None;
+        let mut binary = false;
+        let mut describe = false;
+        let mut search_descriptions = false;
+
+        // subcommands are only recognized as the first argument
+        match args_iter
+            .next_if(|arg| SUBCOMMANDS.contains(&arg.as_str()))
+            .as_deref()
+        {
+            Some("diff") => mode = Mode::Diff,
+            Some("doctor") => mode = Mode::Doctor,
+            Some("audit") => mode = Mode::Audit,
+            _ => {}
+        }
 
         while let Some(arg) = args_iter.next() {
-            match arg.as_str() {
+            // split `--name=value` into the option name and its inline value
+            let (name, mut inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = |hint: &str| {
+                inline_value
+                    .take()
+                    .or_else(|| args_iter.next())
+                    .ok_or(format!("{} requires a value{}", name, hint))
+            };
+
+            match name {
                 "-r" | "--regex" => regex = true,
+                "-1" | "--first" => first = true,
+                "--rank" => rank = true,
+                "--group" => group = true,
+                "--show-empty" => show_empty = true,
+                "--broken" => set_mode(&mut mode, Mode::Broken)?,
+                "--broken-scripts" => set_mode(&mut mode, Mode::BrokenScripts)?,
+                "--missing-libs" => set_mode(&mut mode, Mode::MissingLibs)?,
+                "--privileged" => set_mode(&mut mode, Mode::Privileged)?,
+                "--shadowed" => set_mode(&mut mode, Mode::Shadowed)?,
                 "-h" | "--help" => {
                     print_help();
                     process::exit(0);
@@ -106,36 +399,234 @@ impl Args {
                     println!("pathsearch {}", env!("CARGO_PKG_VERSION"));
                     process::exit(0);
                 }
-                "--color" => {
-                    let value = args_iter
-                        .next()
-                        .ok_or("--color requires a value (auto, always, never)")?;
-                    color = parse_color_option(&value)?;
+                "--color" => color = parse_color_option(&value(" (auto, always, never)")?)?,
+                "--dirs" => dir_range = value(" (e.g. 3, 0..5, 2..)")?.parse()?,
+                "--from" if mode == Mode::Diff => diff_from = Some(value("")?),
+                "--to" if mode == Mode::Diff => diff_to = Some(value("")?),
+                "--var" => var = value(" (e.g. LD_LIBRARY_PATH)")?,
+                "--login-shell" => {
+                    // a separate value is optional, so it is only taken when
+                    // it is clearly a shell and not the pattern
+                    login_shell = Some(
+                        inline_value
+                            .take()
+                            .or_else(|| args_iter.next_if(|arg| login_shell::names_shell(arg))),
+                    );
                 }
-                s if s.starts_with("--color=") => {
-                    let value = &s["--color=".len()..];
-                    color = parse_color_option(value)?;
+                "--pid" => {
+                    let value = value("")?;
+                    pid = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid PID '{}'", value))?,
+                    );
                 }
-                s if s.starts_with("-") => {
-                    return Err(format!("Unknown option: {}", s));
+                "--emit-clean-path" => {
+                    syntax = inline_value.take().map(|s| s.parse()).transpose()?;
+                    set_mode(&mut mode, Mode::CleanPath)?;
+                }
+                "--as-user" => as_user = Some(value("")?),
+                "--groups" => groups = Some(value(" (e.g. wheel,docker)")?),
+                "--dir-timeout" => {
+                    dir_timeout = Some(dir_reader::parse_duration(&value(" (e.g. 500ms)")?)?)
                 }
-                s => {
-                    if pattern.is_some() {
+                "--timings" => timings = true,
+                "--links-against" => links_against = Some(value(" (e.g. libssl.so.1.1)")?),
+                "--imports" => imports = Some(value(" (e.g. SSL_read)")?),
+                "--interpreter" => interpreter = Some(value(" (e.g. python2)")?),
+                "--contains" => contains = Some(value("")?),
+                "--binary" => binary = true,
+                "--describe" => describe = true,
+                "-D" | "--search-descriptions" => search_descriptions = true,
+                "--path" => path = Some(value(" (e.g. /usr/bin:/bin)")?),
+                "--prepend" => {
+                    prepend.push(PathBuf::from(value("")?));
+                    set_mode(&mut mode, Mode::Preview)?;
+                }
+                "--append" => {
+                    append.push(PathBuf::from(value("")?));
+                    set_mode(&mut mode, Mode::Preview)?;
+                }
+                "--" => {
+                    if let Some(s) = args_iter.next() {
+                        set_pattern(&mut pattern, s)?;
+                    }
+                    if args_iter.peek().is_some() {
                         return Err("Multiple patterns provided".to_string());
                     }
-                    pattern = Some(s.to_string());
                 }
+                s if s.starts_with("-") => {
+                    return Err(format!("Unknown option: {}", s));
+                }
+                // likely a subcommand after options, which would otherwise
+                // quietly search for a command of that name
+                s if SUBCOMMANDS.contains(&s) => {
+                    return Err(format!(
+                        "{0} must come before any options, as in 'pathsearch {0} [OPTIONS]'; \
+                         use '-- {0}' to search for a command named {0}",
+                        s
+                    ));
+                }
+                _ => set_pattern(&mut pattern, arg.clone())?,
+            }
+
+            if inline_value.is_some() {
+                return Err(format!("{} does not take a value", name));
+            }
+        }
+
+        let sources = [path.is_some(), login_shell.is_some(), pid.is_some()];
+        if sources.iter().filter(|&&given| given).count() > 1 {
+            return Err("Only one of --path, --login-shell and --pid can be used".to_string());
+        }
+        if mode == Mode::Diff && diff_from.is_none() && diff_to.is_none() {
+            return Err("diff requires --from and/or --to".to_string());
+        }
+        // these modes look at the whole path list
+        if matches!(mode, Mode::Doctor | Mode::CleanPath | Mode::Audit) {
+            if pattern.is_some() {
+                return Err(format!("{} does not take a pattern", mode.flag()));
+            }
+            if dir_range != DirRange::default() {
+                return Err(format!("--dirs cannot be used with {}", mode.flag()));
+            }
+            if dir_timeout.is_some() {
+                return Err(format!("--dir-timeout cannot be used with {}", mode.flag()));
             }
+            if timings {
+                return Err(format!("--timings cannot be used with {}", mode.flag()));
+            }
+        }
+        if first && mode != Mode::Search {
+            return Err(format!("--first cannot be used with {}", mode.flag()));
+        }
+        if rank && !matches!(mode, Mode::Search | Mode::Shadowed) {
+            return Err(format!("--rank cannot be used with {}", mode.flag()));
+        }
+        if group && mode != Mode::Search {
+            return Err(format!("--group cannot be used with {}", mode.flag()));
+        }
+        if mode != Mode::Search {
+            if links_against.is_some() {
+                return Err(format!(
+                    "--links-against cannot be used with {}",
+                    mode.flag()
+                ));
+            }
+            if imports.is_some() {
+                return Err(format!("--imports cannot be used with {}", mode.flag()));
+            }
+            if interpreter.is_some() {
+                return Err(format!("--interpreter cannot be used with {}", mode.flag()));
+            }
+            if contains.is_some() {
+                return Err(format!("--contains cannot be used with {}", mode.flag()));
+            }
+            if describe {
+                return Err(format!("--describe cannot be used with {}", mode.flag()));
+            }
+            if search_descriptions {
+                return Err(format!(
+                    "--search-descriptions cannot be used with {}",
+                    mode.flag()
+                ));
+            }
+        }
+        if show_empty && !group {
+            return Err("--show-empty requires --group".to_string());
+        }
+        if binary && contains.is_none() {
+            return Err("--binary requires --contains".to_string());
+        }
+        // grouped names are laid out in columns, with no room for descriptions
+        if group && (describe || search_descriptions) {
+            let flag = if describe {
+                "--describe"
+            } else {
+                "--search-descriptions"
+            };
+            return Err(format!("{} cannot be used with --group", flag));
         }
 
         Ok(Args {
             pattern,
             regex,
             color,
+            mode,
+            first,
+            rank,
+            dir_range,
+            group,
+            show_empty,
+            diff_from,
+            diff_to,
+            prepend,
+            append,
+            path,
+            var,
+            login_shell,
+            pid,
+            syntax,
+            as_user,
+            groups,
+            dir_timeout,
+            timings,
+            links_against,
+            imports,
+            interpreter,
+            contains,
+            binary,
+            describe,
+            search_descriptions,
         })
     }
 }
 
+/// Names taken as subcommands rather than the pattern
+const SUBCOMMANDS: [&str; 3] = ["diff", "doctor", "audit"];
+
+fn set_pattern(pattern: &mut Option<String>, s: String) -> Result<(), String> {
+    if pattern.is_some() {
+        return Err("Multiple patterns provided".to_string());
+    }
+    *pattern = Some(s);
+    Ok(())
+}
+
+/// Print an error and exit with status 1.
+fn exit_with_error(err: &str) -> ! {
+    eprintln!("Error: {}", err);
+    process::exit(1);
+}
+
+/// Width of the terminal on stdout, or `None` if stdout is not a TTY.
+///
+/// `COLUMNS` takes precedence over the size reported by the terminal, as
+/// with `ls`.
+fn terminal_columns() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(sys::terminal_width)
+        .or(Some(80))
+}
+
+/// Select a mode, rejecting combinations of different modes.
+fn set_mode(mode: &mut Mode, new: Mode) -> Result<(), String> {
+    if *mode != Mode::Search && *mode != new {
+        return Err(format!(
+            "{} cannot be used with {}",
+            new.flag(),
+            mode.flag()
+        ));
+    }
+    *mode = new;
+    Ok(())
+}
+
 fn parse_color_option(s: &str) -> Result<ColorOption, String> {
     match s {
         "auto" => Ok(ColorOption::Auto),
@@ -154,15 +645,97 @@ fn print_help() {
     println!();
     println!("USAGE:");
     println!("    pathsearch [OPTIONS] <pattern>");
+    println!("    pathsearch diff [--from <PATH>] [--to <PATH>] [OPTIONS] <pattern>");
+    println!("    pathsearch doctor [OPTIONS]");
+    println!("    pathsearch audit [OPTIONS]");
     println!();
     println!("ARGUMENTS:");
     println!("    <pattern>    Search pattern (substring, or regex with -r)");
     println!();
     println!("OPTIONS:");
-    println!("    -r, --regex              Interpret pattern as regex");
-    println!("        --color <WHEN>       Control color output [auto, always, never]");
-    println!("    -h, --help               Print help");
-    println!("    -V, --version            Print version");
+    println!("    -r, --regex                Interpret pattern and content patterns as regex");
+    println!(
+        "    -1, --first                Only show the entry the shell would run for each name"
+    );
+    println!(
+        "        --as-user <USER>       Decide what is executable as USER instead of yourself"
+    );
+    println!("        --groups <LIST>        Decide what is executable with the groups in LIST");
+    println!("        --color <WHEN>         Control color output [auto, always, never]");
+    println!("        --login-shell [SHELL]  Search the PATH of a login shell (default: $SHELL)");
+    println!("        --pid <PID>            Search the PATH of a running process");
+    println!("        --var <NAME>           Search the path-list variable NAME instead of PATH");
+    println!(
+        "        --path <LIST>          Search the colon-separated LIST instead of $PATH or --var"
+    );
+    println!("        --rank                 Prefix results with PATH index and per-name rank");
+    println!(
+        "        --dirs <RANGE>         Only search PATH entries in RANGE (e.g. 3, 0..5, 2..)"
+    );
+    println!("        --group                Group results under a header for each directory");
+    println!("        --show-empty           With --group, also list directories without matches");
+    println!(
+        "        --links-against <LIB>  Only show ELF files linked against a library matching LIB"
+    );
+    println!(
+        "        --imports <SYMBOL>     Only show ELF files importing the dynamic symbol SYMBOL"
+    );
+    println!("        --interpreter <PROG>   Only show scripts whose #! interpreter matches PROG");
+    println!("        --contains <TEXT>      Only show text files with a line matching TEXT");
+    println!("        --binary               With --contains, also search binary files");
+    println!("        --describe             Show the man page description of each command");
+    println!("    -D, --search-descriptions  Match the pattern against man page descriptions too;");
+    println!("                               slower, as the page of every command is read");
+    println!("        --shadowed             Only list names found in more than one directory");
+    println!("        --prepend <DIR>        Preview prepending DIR to PATH (repeatable)");
+    println!("        --append <DIR>         Preview appending DIR to PATH (repeatable)");
+    println!(
+        "        --broken               List dangling symlinks and entries that cannot be stat'ed"
+    );
+    println!(
+        "        --broken-scripts       List scripts whose #! interpreter is missing or unusable"
+    );
+    println!(
+        "        --missing-libs         List executables whose shared libraries cannot be found"
+    );
+    println!("        --privileged           List setuid, setgid and file-capability executables");
+    println!(
+        "        --dir-timeout <TIME>   Skip directories not listed within TIME (e.g. 500ms, 2s)"
+    );
+    println!("        --timings              Report how long each directory took to list");
+    println!("        --emit-clean-path[=SHELL]");
+    println!(
+        "                               Print PATH without dead entries as a bash, zsh or fish command"
+    );
+    println!("    -h, --help                 Print help");
+    println!("    -V, --version              Print version");
+    println!();
+    println!("DIFF OPTIONS:");
+    println!("        --from <PATH>          Old PATH value (default: $PATH or --path)");
+    println!("        --to <PATH>            New PATH value (default: $PATH or --path)");
+}
+
+/// Print the number of findings of each severity after `doctor` or
+/// `audit`. Fails if any of them are errors.
+fn print_summary(
+    output: &mut impl Write,
+    var: &str,
+    severities: impl Iterator<Item = doctor::Severity>,
+) -> process::ExitCode {
+    let mut counts = [0; 3];
+    for severity in severities {
+        counts[severity as usize] += 1;
+    }
+    let [notes, warnings, errors] = counts;
+    let _ = writeln!(
+        output,
+        "{}: {} errors, {} warnings, {} notes",
+        var, errors, warnings, notes
+    );
+    match errors {
+        0 => process::ExitCode::SUCCESS,
+        _ => process::ExitCode::FAILURE,
+    }
 }
 
 fn main() -> process::ExitCode {
@@ -189,316 +762,330 @@ fn main() -> process::ExitCode {
     };
 
     let output = FormattedOutput::new(config.color);
+    let reader = DirReader::new(config.dir_timeout);
     let mut output_handle = io::stdout().lock();
 
     // Iterate PATH directories in order. First match = what the shell would execute.
-    for dir in config.dirs {
-        let files = match fs::read_dir(&dir) {
-            Ok(files) => files,
-            Err(_) => {
-                // users often have nonexistent directories in their PATH, silently ignore them
-                continue;
-            }
+    let dirs: &Vec<PathBuf> = &config.entries.iter().map(|e| e.dir.clone()).collect();
+    // with -D, every name is listed, and the pattern is matched against
+    // names and descriptions afterwards
+    let list_filter: &dyn FileNameFilter = match config.search_descriptions {
+        true => &MatchAllFilter::default(),
+        false => filename_filter.as_ref(),
+    };
+    let man_pages = config
+        .describe
+        .then(|| man::ManPages::load(env::var_os("MANPATH").as_deref(), dirs, &reader));
+    // description of a match, or `None` to drop it when -D finds neither
+    // its name nor its description matching
+    let describe = |m: &mut search::Match| -> Option<Option<String>> {
+        let Some(man_pages) = &man_pages else {
+            return Some(None);
         };
-
-        let dir_str = normalize_dir(&dir);
-
-        for file in files {
-            let file_ref = match file.as_ref() {
-                Ok(dir_entry) => dir_entry,
-                Err(err) => {
-                    eprintln!("Failed to get directory entry in '{}': {}", &dir_str, err);
-                    continue;
+        let description = man_pages.describe(&m.file_name);
+        if config.search_descriptions {
+            match filename_filter.filter(&m.file_name) {
+                FilterResult::Matched(range) => m.range = range,
+                FilterResult::NoMatch => {
+                    let matched = filename_filter.filter(description.as_deref()?);
+                    if matched == FilterResult::NoMatch {
+                        return None;
+                    }
                 }
-            };
-            let file_name = file_ref.file_name().display().to_string();
-            let filter_result = filename_filter.filter(&file_name);
-
-            if let FilterResult::Matched(match_range) = filter_result {
-                output.print(&mut output_handle, &dir_str, &file_name, match_range);
             }
         }
-    }
-
-    process::ExitCode::SUCCESS
-}
-
-/// Normalize a directory path by removing trailing separators.
-///
-/// Converts the path to `String`, stripping any trailing path separators
-/// (`MAIN_SEPARATOR`).
-fn normalize_dir(pb: &Path) -> String {
-    let s = pb.display().to_string();
-    s.trim_end_matches(MAIN_SEPARATOR).to_string()
-}
-
-struct FormattedOutput {
-    /// ANSI color code for the directory portion of the path
-    ///
-    /// The general idea is to make the directory portion fade into the
-    /// background a bit so that the user can more easily see the matched
-    /// filenames. It still needs to be legible since it provides
-    /// important information.
-    dir_ansi: &'static str,
-    /// ANSI color code for the foreground color of the matched range
-    match_ansi: &'static str,
-    /// ANSI reset code
-    reset_ansi: &'static str,
-}
-
-impl FormattedOutput {
-    fn new(color: bool) -> Self {
-        match color {
-            true => Self {
-                // decreased intensity
-                dir_ansi: "\x1B[2m",
-                // bold red foreground
-                match_ansi: "\x1B[1;31m",
-                reset_ansi: "\x1B[0m",
-            },
-            false => Self {
-                dir_ansi: "",
-                match_ansi: "",
-                reset_ansi: "",
-            },
-        }
-    }
-
-    /// Print a matching file path with optional color highlighting.
-    fn print(&self, output: &mut impl Write, dir: &str, file: &str, range: MatchRange) {
-        // write directory with dimmed color
-        let _ = write!(
-            output,
-            "{}{}{}{}",
-            self.dir_ansi, dir, MAIN_SEPARATOR, self.reset_ansi
-        );
-
-        // write filename with match range highlighting
-        let filename = file.as_bytes();
-        match range {
-            MatchRange::None => {
-                let _ = output.write(filename);
-            }
-            MatchRange::Range(start, end) => {
-                let _ = output.write_all(&filename[..start]);
-                let _ = write!(output, "{}", self.match_ansi);
-                let _ = output.write_all(&filename[start..end]);
-                let _ = write!(output, "{}", self.reset_ansi);
-                let _ = output.write_all(&filename[end..]);
+        Some(description)
+    };
+    // only PATH entries need to be executable to win
+    let commands_only = config.var == "PATH";
+    let user = config.user.as_ref();
+    // results from the cwd, relative or `~` entries are marked, as are
+    // results from noexec or network mounts, and files the user of
+    // --as-user cannot run
+    let mounts = mounts::Mounts::load();
+    // looked up once a directory has been listed, and not at all for a
+    // hung mount --dir-timeout gave up on
+    let dir_notes: Vec<OnceCell<Vec<String>>> =
+        config.entries.iter().map(|_| OnceCell::new()).collect();
+    let dir_notes = |dir_index: usize| {
+        dir_notes[dir_index].get_or_init(|| {
+            let entry = &config.entries[dir_index];
+            let mut notes: Vec<String> = entry.kind.note().map(String::from).into_iter().collect();
+            if reader.answers(&entry.dir)
+                && let Some(mount) = mounts.find(&entry.dir)
+            {
+                if commands_only && mount.is_noexec() {
+                    notes.push("noexec mount".to_string());
+                }
+                if mount.is_network() {
+                    notes.push(format!("{} mount", mount.fs_type));
+                }
             }
+            notes
+        })
+    };
+    // notes on the file itself, which --group shows without those of its
+    // directory
+    let file_notes = |m: &search::Match| {
+        let mut notes = Vec::new();
+        if let Some(user) = user
+            && commands_only
+            && fs::metadata(m.path()).is_ok_and(|meta| meta.is_file())
+            && !user.can_execute(&m.path())
+        {
+            notes.push(format!("not executable by {}", user.name));
         }
+        notes
+    };
+    let notes = |m: &search::Match| {
+        let mut notes = dir_notes(m.dir_index).clone();
+        notes.extend(file_notes(m));
+        notes
+    };
+    let range = config.dir_range;
+    match config.mode {
+        Mode::Search if config.group => {
+            let mut resolver = search::Resolver::new(commands_only, user);
+            let mut first_group = true;
+            for (dir_index, dir) in dirs.iter().enumerate() {
+                if !range.contains(dir_index) {
+                    continue;
+                }
+                let Some(mut matches) =
+                    search::search_dir(&reader, dir_index, dir, filename_filter.as_ref())
+                else {
+                    continue;
+                };
+                if config.first {
+                    matches.retain(|m| resolver.is_winner(m));
+                }
+                if !config.content_filter.is_empty() {
+                    matches.retain(|m| config.content_filter.check(&m.path(), &reader).is_some());
+                }
+                if matches.is_empty() && !config.show_empty {
+                    continue;
+                }
 
-        let _ = writeln!(output, "{}", self.reset_ansi);
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    // ========================================
-    //  normalize_dir tests
-    // ========================================
-
-    mod normalize_dir {
-        use super::*;
-        use std::path::{MAIN_SEPARATOR as SEP, PathBuf};
-
-        #[test]
-        fn no_path_separator_suffix() {
-            const DIR: &str = "/fake/dir";
-            let pb = PathBuf::from(DIR);
-            let normalized = normalize_dir(&pb);
-            assert_eq!(normalized, DIR);
-        }
-
-        #[test]
-        fn remove_path_separator_suffix() {
-            const DIR: &str = "/fake/dir";
-            let pb = PathBuf::from(format!("{DIR}{SEP}"));
-            let normalized = normalize_dir(&pb);
-            assert_eq!(normalized, DIR);
+                // blank line between groups, like `ls` with several directories
+                if !first_group {
+                    let _ = writeln!(output_handle);
+                }
+                first_group = false;
+                if config.rank {
+                    output.print_rank(&mut output_handle, dir_index, (1, 1));
+                }
+                output.print_group_header(
+                    &mut output_handle,
+                    &search::normalize_dir(dir),
+                    dir_notes(dir_index),
+                );
+                let notes: Vec<Vec<String>> = matches.iter().map(file_notes).collect();
+                output.print_group(&mut output_handle, &matches, &notes, config.columns);
+            }
         }
-
-        #[test]
-        fn remove_multiple_path_separator_suffix() {
-            const DIR: &str = "/fake/dir";
-            let pb = PathBuf::from(format!("{DIR}{SEP}{SEP}{SEP}"));
-            let normalized = normalize_dir(&pb);
-            assert_eq!(normalized, DIR);
+        Mode::Search if config.rank => {
+            // ranks need the total number of copies of each name up front
+            let mut matches = Vec::new();
+            search::search(&reader, dirs, range, list_filter, |m| matches.push(m));
+            let ranks = search::rank_by_name(&matches);
+            let mut resolver = search::Resolver::new(commands_only, user);
+            for (m, rank) in matches.iter_mut().zip(ranks) {
+                if config.first && !resolver.is_winner(m) {
+                    continue;
+                }
+                let Some(content_notes) = config.content_filter.check(&m.path(), &reader) else {
+                    continue;
+                };
+                let Some(description) = describe(m) else {
+                    continue;
+                };
+                output.print_rank(&mut output_handle, m.dir_index, rank);
+                let mut notes = notes(m);
+                notes.extend(content_notes);
+                output.print_described(&mut output_handle, m, &notes, description.as_deref());
+            }
         }
-    }
-
-    // ========================================
-    // FormattedOutput tests
-    // ========================================
-
-    mod formatted_output {
-        use super::*;
-        use std::path::MAIN_SEPARATOR as SEP;
-
-        // ANSI escape code constants for test assertions
-        const DIM: &str = "\x1B[2m";
-        const BOLD_RED: &str = "\x1B[1;31m";
-        const RESET: &str = "\x1B[0m";
-
-        // --- Construction tests ---
-
-        #[test]
-        fn new_with_color_enabled() {
-            let output = FormattedOutput::new(true);
-            assert_eq!(output.dir_ansi, DIM);
-            assert_eq!(output.match_ansi, BOLD_RED);
-            assert_eq!(output.reset_ansi, RESET);
+        Mode::Search => {
+            let mut resolver = search::Resolver::new(commands_only, user);
+            search::search(&reader, dirs, range, list_filter, |mut m| {
+                if config.first && !resolver.is_winner(&m) {
+                    return;
+                }
+                let Some(content_notes) = config.content_filter.check(&m.path(), &reader) else {
+                    return;
+                };
+                let Some(description) = describe(&mut m) else {
+                    return;
+                };
+                let mut notes = notes(&m);
+                notes.extend(content_notes);
+                output.print_described(&mut output_handle, &m, &notes, description.as_deref());
+            })
         }
-
-        #[test]
-        fn new_with_color_disabled() {
-            let output = FormattedOutput::new(false);
-            assert_eq!(output.dir_ansi, "");
-            assert_eq!(output.match_ansi, "");
-            assert_eq!(output.reset_ansi, "");
+        Mode::Broken => search::search(&reader, dirs, range, filename_filter.as_ref(), |m| {
+            if let Some(breakage) = broken::check(&m.path()) {
+                output.print_broken(&mut output_handle, &m, &breakage);
+            }
+        }),
+        Mode::BrokenScripts => {
+            search::search(&reader, dirs, range, filename_filter.as_ref(), |m| {
+                let problems = shebang::check(&m.path(), dirs, &reader);
+                if !problems.is_empty() {
+                    output.print_problems(&mut output_handle, &m, &problems);
+                }
+            })
         }
-
-        // --- Print output tests (no color) ---
-
-        #[test]
-        fn print_no_color_no_match_range() {
-            let output = FormattedOutput::new(false);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "ls", MatchRange::None);
-            assert_eq!(
-                String::from_utf8(buf).unwrap(),
-                format!("/usr/bin{SEP}ls\n")
-            );
+        Mode::MissingLibs => {
+            let linker = libraries::Linker::load();
+            search::search(&reader, dirs, range, filename_filter.as_ref(), |m| {
+                let missing = linker.missing(&m.path());
+                if !missing.is_empty() {
+                    output.print_problems(&mut output_handle, &m, &missing);
+                }
+            })
         }
-
-        #[test]
-        fn print_no_color_with_match_range() {
-            let output = FormattedOutput::new(false);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(0, 4));
-            assert_eq!(
-                String::from_utf8(buf).unwrap(),
-                format!("/usr/bin{SEP}grep\n")
+        Mode::Privileged => search::search(&reader, dirs, range, filename_filter.as_ref(), |m| {
+            match privileged::check(&m.path()) {
+                Ok(Some(privileges)) => {
+                    output.print_privileged(&mut output_handle, &m, &privileges)
+                }
+                Ok(None) => {}
+                // dangling symlinks are for --broken to report
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => eprintln!("Cannot check {}: {}", m.path().display(), err),
+            }
+        }),
+        Mode::Diff => {
+            let old = search::resolve(
+                &reader,
+                &config.diff_from,
+                filename_filter.as_ref(),
+                commands_only,
+                user,
             );
+            let new = search::resolve(&reader, dirs, filename_filter.as_ref(), commands_only, user);
+            for change in diff::diff(&old, &new) {
+                output.print_change(&mut output_handle, &change);
+            }
         }
+        Mode::Preview => {
+            let filter = filename_filter.as_ref();
+            let added_dirs: Vec<PathBuf> = config
+                .prepend
+                .iter()
+                .chain(&config.append)
+                .cloned()
+                .collect();
+            let modified: Vec<PathBuf> = config
+                .prepend
+                .iter()
+                .chain(dirs)
+                .chain(&config.append)
+                .cloned()
+                .collect();
 
-        // --- Print output tests (with color) ---
-
-        #[test]
-        fn print_color_no_match_range() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "ls", MatchRange::None);
-            let result = String::from_utf8(buf).unwrap();
-            // Directory should be dimmed, filename plain, ends with reset
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}ls{RESET}\n");
-            assert_eq!(result, expected);
-        }
-
-        #[test]
-        fn print_color_match_at_start() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(0, 2));
-            let result = String::from_utf8(buf).unwrap();
-            // "gr" highlighted, "ep" plain
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}{BOLD_RED}gr{RESET}ep{RESET}\n");
-            assert_eq!(result, expected);
-        }
-
-        #[test]
-        fn print_color_match_at_end() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(2, 4));
-            let result = String::from_utf8(buf).unwrap();
-            // "gr" plain, "ep" highlighted
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}gr{BOLD_RED}ep{RESET}{RESET}\n");
-            assert_eq!(result, expected);
-        }
+            let old = search::resolve(&reader, dirs, filter, commands_only, user);
+            let new = search::resolve(&reader, &modified, filter, commands_only, user);
+            let added = search::resolve(&reader, &added_dirs, filter, commands_only, user);
 
-        #[test]
-        fn print_color_match_in_middle() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "cargo", MatchRange::Range(1, 3));
-            let result = String::from_utf8(buf).unwrap();
-            // "c" plain, "ar" highlighted, "go" plain
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}c{BOLD_RED}ar{RESET}go{RESET}\n");
-            assert_eq!(result, expected);
+            // commands the new directories take over
+            for change in diff::diff(&old, &new) {
+                if matches!(change, diff::Change::Changed { .. }) {
+                    output.print_change(&mut output_handle, &change);
+                }
+            }
+            // commands in the new directories that existing ones still win
+            for shadowed in diff::shadowed(&added, &old, &new) {
+                output.print_shadowed(&mut output_handle, &shadowed);
+            }
         }
-
-        #[test]
-        fn print_color_full_filename_match() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "ls", MatchRange::Range(0, 2));
-            let result = String::from_utf8(buf).unwrap();
-            // Entire "ls" highlighted
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}{BOLD_RED}ls{RESET}{RESET}\n");
-            assert_eq!(result, expected);
+        Mode::Doctor => {
+            let findings = doctor::check(&config.entries, commands_only);
+            for finding in &findings {
+                let entry = &config.entries[finding.index];
+                output.print_finding(&mut output_handle, entry, finding);
+            }
+            let severities = findings.iter().map(|finding| finding.problem.severity());
+            return print_summary(&mut output_handle, &config.var, severities);
         }
-
-        // --- Edge case tests ---
-
-        #[test]
-        fn print_empty_filename() {
-            let output = FormattedOutput::new(false);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "", MatchRange::None);
-            assert_eq!(String::from_utf8(buf).unwrap(), format!("/usr/bin{SEP}\n"));
+        Mode::Audit => {
+            let findings = audit::audit(&config.entries, sys::effective_uid());
+            for finding in &findings {
+                output.print_audit_finding(&mut output_handle, finding);
+            }
+            let severities = findings.iter().map(audit::Finding::severity);
+            return print_summary(&mut output_handle, &config.var, severities);
         }
+        Mode::CleanPath => {
+            let cleaned = clean_path::clean(&config.entries, commands_only);
+            for finding in &cleaned.removed {
+                let entry = &config.entries[finding.index];
+                eprintln!(
+                    "Removed [{}] {}: {}",
+                    finding.index,
+                    entry.raw.display(),
+                    finding.problem
+                );
+            }
 
-        #[test]
-        fn print_filename_with_ansi_escape_in_name() {
-            // Filenames could theoretically contain ANSI sequences
-            let output = FormattedOutput::new(false);
-            let mut buf = Vec::new();
-            let filename = "file\x1B[31mred";
-            output.print(&mut buf, "/tmp", filename, MatchRange::None);
-            // Should pass through unchanged (no sanitization)
-            assert_eq!(
-                String::from_utf8(buf).unwrap(),
-                format!("/tmp{SEP}file\x1B[31mred\n")
+            // check that every command still resolves to the same file
+            let new_dirs: Vec<PathBuf> = cleaned.kept.iter().map(|e| e.dir.clone()).collect();
+            let old = search::resolve(&reader, dirs, filename_filter.as_ref(), commands_only, user);
+            let new = search::resolve(
+                &reader,
+                &new_dirs,
+                filename_filter.as_ref(),
+                commands_only,
+                user,
+            );
+            let changes = diff::diff(&old, &new);
+            let command = clean_path::export_command(&config.var, &cleaned.kept, config.syntax)
+                .unwrap_or_else(|err| exit_with_error(&err));
+            let _ = writeln!(output_handle, "{}", command);
+            if !changes.is_empty() {
+                eprintln!("Resolution would change for {} names:", changes.len());
+                let stderr_output = FormattedOutput::new(false);
+                for change in &changes {
+                    stderr_output.print_change(&mut io::stderr(), change);
+                }
+                return process::ExitCode::FAILURE;
+            }
+            eprintln!(
+                "Removed {} of {} entries; all {} names resolve to the same files",
+                cleaned.removed.len(),
+                config.entries.len(),
+                old.len()
             );
         }
-
-        #[test]
-        fn print_empty_directory() {
-            let output = FormattedOutput::new(false);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "", "ls", MatchRange::None);
-            assert_eq!(String::from_utf8(buf).unwrap(), format!("{SEP}ls\n"));
-        }
-
-        // --- Match range boundary tests ---
-
-        #[test]
-        fn print_match_range_single_byte() {
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            output.print(&mut buf, "/usr/bin", "abc", MatchRange::Range(1, 2));
-            let result = String::from_utf8(buf).unwrap();
-            // "a" plain, "b" highlighted, "c" plain
-            let expected = format!("{DIM}/usr/bin{SEP}{RESET}a{BOLD_RED}b{RESET}c{RESET}\n");
-            assert_eq!(result, expected);
+        Mode::Shadowed => {
+            let mut matches = Vec::new();
+            search::search(&reader, dirs, range, filename_filter.as_ref(), |m| {
+                matches.push(m)
+            });
+            for group in search::group_by_name(matches) {
+                if group.len() < 2 {
+                    continue;
+                }
+                // winning copy first, shadowed copies indented under it
+                for (i, m) in group.iter().enumerate() {
+                    if i > 0 {
+                        let _ = write!(output_handle, "    ");
+                    }
+                    if config.rank {
+                        output.print_rank(&mut output_handle, m.dir_index, (i + 1, group.len()));
+                    }
+                    output.print(&mut output_handle, &m.dir, &m.file_name, m.range, &notes(m));
+                }
+            }
         }
+    }
 
-        #[test]
-        fn print_match_range_with_multibyte_utf8() {
-            // Test that byte-based slicing works correctly with UTF-8
-            let output = FormattedOutput::new(true);
-            let mut buf = Vec::new();
-            // "café" - the 'é' is 2 bytes (0xc3 0xa9)
-            let filename = "café";
-            // Match "af" which spans bytes 1-3 (the 'a' and first byte of 'é')
-            // This tests that we're doing byte slicing, not character slicing
-            output.print(&mut buf, "/tmp", filename, MatchRange::Range(1, 3));
-            // The output will slice at byte boundaries
-            // "c" then highlighted "af" (bytes 1-3) then "é" remainder
-            assert!(!buf.is_empty()); // Just verify it doesn't panic
+    if config.timings {
+        let stderr_output = FormattedOutput::new(false);
+        for timing in reader.take_timings() {
+            stderr_output.print_timing(&mut io::stderr(), &timing);
         }
     }
+
+    process::ExitCode::SUCCESS
 }
@@ -0,0 +1,450 @@
+//! One-line descriptions of commands from their man pages.
+//!
+//! Like `whatis`, but without its database: the NAME section of each page
+//! is read on demand, from the `man1`, `man8` and `man6` directories of
+//! MANPATH, or of the default man directories and those next to the
+//! searched `bin` directories.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::dir_reader::DirReader;
+use crate::path_var;
+
+/// Sections with commands, in the order they are searched
+const SECTIONS: [&str; 3] = ["1", "8", "6"];
+/// Bytes of a decompressed page read looking for the NAME section, which
+/// comes right after the title
+const HEAD_SIZE: u64 = 16 << 10;
+
+/// Man pages of commands, by command name.
+pub struct ManPages {
+    pages: HashMap<String, PathBuf>,
+    descriptions: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl ManPages {
+    /// Index the pages in `manpath` (the value of MANPATH), or if unset,
+    /// the default man directories and the `share/man` and `man`
+    /// directories next to each of `bin_dirs`. Directories are listed with
+    /// `reader`, and those next to a `bin` directory it gave up on are
+    /// skipped, being on the same hung mount.
+    pub fn load(manpath: Option<&OsStr>, bin_dirs: &[PathBuf], reader: &DirReader) -> Self {
+        let mut pages = HashMap::new();
+        for dir in man_dirs(manpath, bin_dirs, |dir| reader.answers(dir)) {
+            for section in SECTIONS {
+                let section_dir = dir.join(format!("man{}", section));
+                let Some(files) = reader.list(&section_dir) else {
+                    if !reader.answers(&section_dir) {
+                        break;
+                    }
+                    continue;
+                };
+                for file in files {
+                    if let Some(name) = page_name(&file.to_string_lossy(), section) {
+                        pages
+                            .entry(name.to_string())
+                            .or_insert_with(|| section_dir.join(&file));
+                    }
+                }
+            }
+        }
+        ManPages {
+            pages,
+            descriptions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Description of the command `name`, e.g. `list directory contents`
+    /// for `ls`, or `None` if it has no page or the page has no NAME
+    /// section.
+    pub fn describe(&self, name: &str) -> Option<String> {
+        if let Some(description) = self.descriptions.borrow().get(name) {
+            return description.clone();
+        }
+        let description = self
+            .pages
+            .get(name)
+            .and_then(|page| read_page(page).ok())
+            .and_then(|text| description(&text));
+        self.descriptions
+            .borrow_mut()
+            .insert(name.to_string(), description.clone());
+        description
+    }
+}
+
+/// Directories to look for pages in, in order. Empty entries of MANPATH
+/// stand for the defaults, as with man-db. Only `bin` directories that
+/// `answers` are used for defaults.
+fn man_dirs(
+    manpath: Option<&OsStr>,
+    bin_dirs: &[PathBuf],
+    answers: impl Fn(&Path) -> bool,
+) -> Vec<PathBuf> {
+    let mut defaults: Vec<PathBuf> = bin_dirs
+        .iter()
+        .filter(|dir| matches!(dir.file_name(), Some(name) if name == "bin" || name == "sbin"))
+        .filter(|dir| answers(dir))
+        .filter_map(|dir| dir.parent())
+        .flat_map(|prefix| [prefix.join("share/man"), prefix.join("man")])
+        .collect();
+    let system = path_var::default_value("MANPATH").unwrap_or_default();
+    defaults.extend(system.split(':').map(PathBuf::from));
+
+    let dirs: Vec<PathBuf> = match manpath {
+        Some(manpath) => manpath
+            .as_bytes()
+            .split(|&b| b == b':')
+            .flat_map(|entry| match entry {
+                b"" => defaults.clone(),
+                entry => vec![PathBuf::from(OsStr::from_bytes(entry))],
+            })
+            .collect(),
+        None => defaults,
+    };
+    let mut unique = Vec::new();
+    for dir in dirs {
+        if !unique.contains(&dir) {
+            unique.push(dir);
+        }
+    }
+    unique
+}
+
+/// Command name of the page file `file_name` in section `section`, e.g.
+/// `ls` for `ls.1.gz` or `openssl` for `openssl.1ssl`.
+fn page_name<'a>(file_name: &'a str, section: &str) -> Option<&'a str> {
+    let page = file_name
+        .strip_suffix(".gz")
+        .or_else(|| file_name.strip_suffix(".xz"))
+        .unwrap_or(file_name);
+    // pages with other compressions, like `ls.1.bz2`, have no section
+    // extension left and cannot be read anyway
+    let (name, extension) = page.rsplit_once('.')?;
+    (!name.is_empty() && extension.starts_with(section)).then_some(name)
+}
+
+/// The start of the page at `path`, decompressed, following one `.so`
+/// link to another page.
+fn read_page(path: &Path) -> io::Result<String> {
+    let text = read_head(path)?;
+    let first_line = text
+        .lines()
+        .find(|line| !line.starts_with(".\\\"") && !line.starts_with("'\\\""));
+    let Some(target) = first_line.and_then(|line| line.strip_prefix(".so ")) else {
+        return Ok(text);
+    };
+    // .so paths are relative to the top of the man directory
+    let root = path
+        .parent()
+        .and_then(Path::parent)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let target = root.join(target.trim());
+    ["", ".gz", ".xz"]
+        .iter()
+        .map(|suffix| PathBuf::from(format!("{}{}", target.display(), suffix)))
+        .find(|path| path.is_file())
+        .map_or_else(
+            || Err(io::ErrorKind::NotFound.into()),
+            |path| read_head(&path),
+        )
+}
+
+/// Up to `HEAD_SIZE` bytes of a page, decompressing by its extension.
+/// Decompression stops there too, so the rest of a page is never inflated.
+fn read_head(path: &Path) -> io::Result<String> {
+    let file = File::open(path)?;
+    let page = match path.extension().and_then(OsStr::to_str) {
+        Some(extension @ ("gz" | "xz")) => decoder(file, extension)?,
+        _ => Box::new(file),
+    };
+    let mut text = Vec::new();
+    page.take(HEAD_SIZE).read_to_end(&mut text)?;
+    Ok(String::from_utf8_lossy(&text).into_owned())
+}
+
+/// Decompressed contents of a page with the extension `gz` or `xz`.
+#[cfg(feature = "compressed-man-pages")]
+fn decoder(file: File, extension: &str) -> io::Result<Box<dyn Read>> {
+    Ok(match extension {
+        "gz" => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        _ => Box::new(liblzma::read::XzDecoder::new_multi_decoder(file)),
+    })
+}
+
+/// Compressed pages cannot be read without the `compressed-man-pages`
+/// feature.
+#[cfg(not(feature = "compressed-man-pages"))]
+fn decoder(_file: File, _extension: &str) -> io::Result<Box<dyn Read>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Description in the NAME section of roff source: what follows `\-` in
+/// `ls \- list directory contents`, or the `.Nd` line of mdoc pages.
+fn description(page: &str) -> Option<String> {
+    let mut in_name = false;
+    let mut words = Vec::new();
+    for line in page.lines() {
+        if let Some(heading) = line
+            .strip_prefix(".SH")
+            .or_else(|| line.strip_prefix(".Sh"))
+        {
+            if in_name {
+                break;
+            }
+            in_name = heading
+                .trim()
+                .trim_matches('"')
+                .eq_ignore_ascii_case("NAME");
+            continue;
+        }
+        if !in_name {
+            continue;
+        }
+        if let Some(description) = line.strip_prefix(".Nd ") {
+            words.push(format!("\\- {}", description));
+        } else if let Some((font, text)) = line.split_once(' ')
+            && matches!(
+                font,
+                ".B" | ".I" | ".BR" | ".BI" | ".IB" | ".IR" | ".RB" | ".RI" | ".Nm"
+            )
+        {
+            words.push(text.replace('"', ""));
+        } else if !line.starts_with(['.', '\'']) {
+            words.push(line.to_string());
+        }
+    }
+
+    let text = unroff(&words.join(" "));
+    let (_, description) = text.split_once(" - ")?;
+    let description = description.trim();
+    (!description.is_empty()).then(|| description.to_string())
+}
+
+/// Plain text of a line of roff: fonts and other escapes removed, and
+/// whitespace collapsed.
+fn unroff(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('-') => text.push('-'),
+            Some('e' | '\\') => text.push('\\'),
+            Some(' ' | '~') => text.push(' '),
+            // comment
+            Some('"') => break,
+            Some('(') => {
+                let name: String = chars.by_ref().take(2).collect();
+                text.push_str(special_char(&name));
+            }
+            Some('[') => {
+                let name: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                text.push_str(special_char(&name));
+            }
+            // fonts and strings: \fB, \f(CW, \f[B], \*(lq
+            Some('f' | '*') => match chars.next() {
+                Some('(') => {
+                    chars.by_ref().nth(1);
+                }
+                Some('[') => {
+                    chars.by_ref().find(|&c| c == ']');
+                }
+                _ => {}
+            },
+            // size changes: \s0, \s-1, \s+2
+            Some('s') => {
+                let rest = chars.as_str();
+                let sign = usize::from(rest.starts_with(['+', '-']));
+                let digits = rest[sign..]
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .count();
+                chars = rest[sign + digits..].chars();
+            }
+            // \& \| \^ \/ \, \c and others print nothing
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Text of a named roff character such as `\(em`.
+fn special_char(name: &str) -> &'static str {
+    match name {
+        "em" | "en" | "hy" | "mi" => "-",
+        "aq" | "oq" | "cq" => "'",
+        "lq" | "rq" | "dq" => "\"",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    #[test]
+    fn description_of_man_pages() {
+        let page = ".\\\" comment\n.TH LS 1\n.SH NAME\nls \\- list directory contents\n.SH SYNOPSIS\n.B ls\n";
+        assert_eq!(
+            description(page),
+            Some("list directory contents".to_string())
+        );
+        let page = ".SH \"NAME\"\n.B gzip, gunzip\n\\- compress or expand\n\\fBfiles\\fR \\(em quickly\n.SH DESCRIPTION\nx \\- y\n";
+        assert_eq!(
+            description(page),
+            Some("compress or expand files - quickly".to_string())
+        );
+        // no NAME section
+        assert_eq!(description(".TH X 1\nx \\- y\n"), None);
+    }
+
+    #[test]
+    fn description_of_mdoc_pages() {
+        let page =
+            ".Dd May 1\n.Dt TAR 1\n.Sh NAME\n.Nm tar\n.Nd manipulate tape archives\n.Sh SYNOPSIS\n";
+        assert_eq!(
+            description(page),
+            Some("manipulate tape archives".to_string())
+        );
+    }
+
+    #[test]
+    fn unroff_escapes() {
+        assert_eq!(
+            unroff("\\fBgit\\-log\\fP \\f(CWshows\\f[] \\s-1commit\\s0 logs\\&"),
+            "git-log shows commit logs"
+        );
+        assert_eq!(
+            unroff("\\(lqquoted\\(rq  \\*(Aqtext \\[aq]x"),
+            "\"quoted\" text 'x"
+        );
+        assert_eq!(unroff("text \\\" comment"), "text");
+    }
+
+    #[test]
+    fn page_names() {
+        assert_eq!(page_name("ls.1.gz", "1"), Some("ls"));
+        assert_eq!(page_name("openssl.1ssl", "1"), Some("openssl"));
+        assert_eq!(page_name("python3.11.1.xz", "1"), Some("python3.11"));
+        assert_eq!(page_name("ip.8", "8"), Some("ip"));
+        assert_eq!(page_name("ls.1.bz2", "1"), None);
+        assert_eq!(page_name("ls.8", "1"), None);
+        assert_eq!(page_name(".1", "1"), None);
+    }
+
+    #[test]
+    fn man_dirs_from_manpath_and_bin_dirs() {
+        let bin_dirs = [
+            PathBuf::from("/opt/tool/bin"),
+            PathBuf::from("/opt/tool/libexec"),
+        ];
+        let dirs = man_dirs(None, &bin_dirs, |_| true);
+        assert_eq!(
+            dirs[..2],
+            [
+                PathBuf::from("/opt/tool/share/man"),
+                PathBuf::from("/opt/tool/man")
+            ]
+        );
+        assert!(dirs.contains(&PathBuf::from("/usr/share/man")));
+
+        // an empty entry stands for the defaults
+        let dirs = man_dirs(Some(OsStr::new("/mine:")), &bin_dirs, |_| true);
+        assert_eq!(
+            dirs[..2],
+            [PathBuf::from("/mine"), PathBuf::from("/opt/tool/share/man")]
+        );
+        assert_eq!(
+            man_dirs(Some(OsStr::new("/mine:/mine")), &bin_dirs, |_| true),
+            [PathBuf::from("/mine")]
+        );
+
+        // nothing is looked up next to a bin directory that hangs
+        let dirs = man_dirs(None, &bin_dirs, |dir| dir != Path::new("/opt/tool/bin"));
+        assert!(!dirs.contains(&PathBuf::from("/opt/tool/share/man")));
+    }
+
+    #[test]
+    fn describe_reads_pages_and_follows_so_links() {
+        let dir = TempDir::new("man-describe");
+        let man1 = dir.path().join("man1");
+        let man8 = dir.path().join("man8");
+        fs::create_dir(&man1).unwrap();
+        fs::create_dir(&man8).unwrap();
+        fs::write(man1.join("tool.1"), ".SH NAME\ntool \\- do things\n").unwrap();
+        fs::write(man1.join("alias.1"), ".so man1/tool.1\n").unwrap();
+        fs::write(
+            man8.join("tool.8"),
+            ".SH NAME\ntool \\- administer things\n",
+        )
+        .unwrap();
+        fs::write(man8.join("daemon.8"), ".SH NAME\ndaemon \\- serve things\n").unwrap();
+
+        let pages = ManPages::load(Some(dir.path().as_os_str()), &[], &DirReader::new(None));
+        // section 1 comes first
+        assert_eq!(pages.describe("tool"), Some("do things".to_string()));
+        assert_eq!(pages.describe("alias"), Some("do things".to_string()));
+        assert_eq!(pages.describe("daemon"), Some("serve things".to_string()));
+        assert_eq!(pages.describe("missing"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "compressed-man-pages")]
+    fn read_head_stops_decompressing_at_head_size() {
+        use std::io::Write;
+
+        let dir = TempDir::new("man-compressed");
+        let page = format!(".SH NAME\ntool \\- do things\n{}", "x".repeat(1 << 20));
+        let gz = dir.path().join("tool.1.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&gz).unwrap(), Default::default());
+        encoder.write_all(page.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        let xz = dir.path().join("tool.1.xz");
+        let mut encoder = liblzma::write::XzEncoder::new(File::create(&xz).unwrap(), 6);
+        encoder.write_all(page.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        for path in [gz, xz] {
+            let text = read_head(&path).unwrap();
+            assert_eq!(text.len() as u64, HEAD_SIZE, "{}", path.display());
+            assert!(page.starts_with(&text));
+        }
+        // a page that is not what its extension says cannot be read
+        let fake = dir.path().join("fake.1.gz");
+        fs::write(&fake, ".SH NAME\nfake \\- not compressed\n").unwrap();
+        assert!(read_head(&fake).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "compressed-man-pages")]
+    fn describe_reads_compressed_pages() {
+        let man1 = Path::new("/usr/share/man/man1");
+        let Some(page) = fs::read_dir(man1).ok().and_then(|files| {
+            files.flatten().map(|file| file.path()).find(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "gz" || ext == "xz")
+            })
+        }) else {
+            return;
+        };
+        let text = read_page(&page).unwrap();
+        assert!(
+            text.contains(".TH") || text.contains(".Dt"),
+            "{}",
+            page.display()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Mount table lookups from `/proc/self/mountinfo`.
+//!
+//! Which mount a directory lives on decides whether anything in it can run
+//! at all (`noexec`), and whether looking through it can stall on the
+//! network.
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    /// Per-mount options, e.g. `rw`, `nosuid`, `noexec`
+    pub options: Vec<String>,
+    /// Filesystem type, e.g. `ext4`, `nfs4` or `fuse.sshfs`
+    pub fs_type: String,
+}
+
+impl Mount {
+    /// Whether the kernel refuses to execute files on this mount.
+    pub fn is_noexec(&self) -> bool {
+        self.options.iter().any(|option| option == "noexec")
+    }
+
+    /// Whether this is a network filesystem (NFS, SMB, or any FUSE
+    /// filesystem such as sshfs), where each lookup may be a round trip.
+    pub fn is_network(&self) -> bool {
+        let fs_type = self.fs_type.as_str();
+        matches!(fs_type, "nfs" | "nfs4" | "cifs" | "smb3" | "smbfs" | "fuse")
+            || fs_type.starts_with("fuse.")
+    }
+}
+
+/// The mounts visible to this process.
+#[derive(Debug, Default)]
+pub struct Mounts {
+    mounts: Vec<Mount>,
+}
+
+impl Mounts {
+    /// Read the mount table of this process. The table is empty if
+    /// `/proc/self/mountinfo` cannot be read.
+    pub fn load() -> Self {
+        fs::read("/proc/self/mountinfo")
+            .map(|mountinfo| Self::parse(&mountinfo))
+            .unwrap_or_default()
+    }
+
+    /// Parse mountinfo lines:
+    /// `id parent major:minor root mount_point options [optional...] - fs_type source super_options`.
+    fn parse(mountinfo: &[u8]) -> Self {
+        let mounts = mountinfo
+            .split(|&b| b == b'\n')
+            .filter_map(|line| {
+                let fields: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
+                let separator = fields.iter().position(|&field| field == b"-")?;
+                if separator < 6 {
+                    return None;
+                }
+                let fs_type = fields.get(separator + 1)?;
+                Some(Mount {
+                    mount_point: PathBuf::from(unescape(fields[4])),
+                    options: String::from_utf8_lossy(fields[5])
+                        .split(',')
+                        .map(str::to_string)
+                        .collect(),
+                    fs_type: String::from_utf8_lossy(fs_type).into_owned(),
+                })
+            })
+            .collect();
+        Mounts { mounts }
+    }
+
+    /// Mount that `dir` lives on, following symlinks. Of several mounts on
+    /// the same point, the last one hides the others.
+    pub fn find(&self, dir: &Path) -> Option<&Mount> {
+        let dir = fs::canonicalize(dir).ok()?;
+        self.mounts
+            .iter()
+            .filter(|mount| dir.starts_with(&mount.mount_point))
+            .max_by_key(|mount| mount.mount_point.components().count())
+    }
+}
+
+/// Undo the octal escapes (`\040` for a space) mountinfo uses for
+/// whitespace and backslashes in paths.
+fn unescape(field: &[u8]) -> OsString {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field
+            .get(i + 1..i + 4)
+            .filter(|digits| field[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match octal {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 4;
+            }
+            None => {
+                bytes.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &[u8] = b"28 1 254:0 / / rw,relatime - ext4 /dev/vda rw\n\
+        29 28 0:40 / /tmp rw,nosuid,nodev,noexec shared:5 - tmpfs tmpfs rw\n\
+        30 28 0:41 / /home/me/my\\040tools rw - fuse.sshfs me@host:/tools rw\n\
+        31 28 0:42 /export /net/share rw,relatime shared:7 master:2 - nfs4 srv:/export rw\n\
+        garbage line\n";
+
+    #[test]
+    fn parse_mountinfo_lines() {
+        let mounts = Mounts::parse(MOUNTINFO).mounts;
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(
+            mounts[1],
+            Mount {
+                mount_point: PathBuf::from("/tmp"),
+                options: vec![
+                    "rw".to_string(),
+                    "nosuid".to_string(),
+                    "nodev".to_string(),
+                    "noexec".to_string()
+                ],
+                fs_type: "tmpfs".to_string(),
+            }
+        );
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/home/me/my tools"));
+        // optional fields before the separator
+        assert_eq!(mounts[3].fs_type, "nfs4");
+    }
+
+    #[test]
+    fn noexec_and_network_mounts() {
+        let mounts = Mounts::parse(MOUNTINFO).mounts;
+        assert!(!mounts[0].is_noexec() && !mounts[0].is_network());
+        assert!(mounts[1].is_noexec() && !mounts[1].is_network());
+        assert!(mounts[2].is_network());
+        assert!(mounts[3].is_network());
+    }
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape(br"a\040b\134c"), OsString::from(r"a b\c"));
+        // not an escape
+        assert_eq!(unescape(br"a\09"), OsString::from(r"a\09"));
+    }
+
+    #[test]
+    fn find_longest_mount_point() {
+        let mounts = Mounts::parse(
+            b"1 0 0:1 / / rw - ext4 a rw\n\
+              2 1 0:2 / / rw - overlay b rw\n\
+              3 1 0:3 / /nonexistent-mount rw - nfs c rw\n",
+        );
+        // the root was mounted over, and only the last mount counts
+        assert_eq!(mounts.find(Path::new("/")).unwrap().fs_type, "overlay");
+        assert_eq!(mounts.find(Path::new("/usr")).unwrap().fs_type, "overlay");
+        // directories that do not exist are on no mount
+        assert_eq!(mounts.find(Path::new("/nonexistent-mount/bin")), None);
+    }
+
+    #[test]
+    fn load_finds_root() {
+        if let Some(mount) = Mounts::load().find(Path::new("/")) {
+            assert_eq!(mount.mount_point, PathBuf::from("/"));
+        }
+    }
+}
@@ -0,0 +1,881 @@
+//! Result formatting.
+//!
+//! Writes matched paths to the terminal, with optional ANSI color for the
+//! directory and the matched part of the filename.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::path::MAIN_SEPARATOR;
+
+use crate::audit;
+use crate::broken::Breakage;
+use crate::diff::{Change, Shadowed};
+use crate::dir_reader::{self, Outcome, Timing};
+use crate::doctor::{Finding, Severity};
+use crate::filename_filter::MatchRange;
+use crate::path_entry::PathEntry;
+use crate::privileged::Privileges;
+use crate::search::Match;
+
+pub struct FormattedOutput {
+    /// ANSI color code for the directory portion of the path
+    ///
+    /// The general idea is to make the directory portion fade into the
+    /// background a bit so that the user can more easily see the matched
+    /// filenames. It still needs to be legible since it provides
+    /// important information.
+    dir_ansi: &'static str,
+    /// ANSI color code for the foreground color of the matched range
+    match_ansi: &'static str,
+    /// ANSI color codes for added, removed and changed commands in diffs
+    added_ansi: &'static str,
+    removed_ansi: &'static str,
+    changed_ansi: &'static str,
+    /// ANSI color code for notes such as `(cwd)` that deserve attention
+    note_ansi: &'static str,
+    /// ANSI reset code
+    reset_ansi: &'static str,
+}
+
+impl FormattedOutput {
+    pub fn new(color: bool) -> Self {
+        match color {
+            true => Self {
+                // decreased intensity
+                dir_ansi: "\x1B[2m",
+                // bold red foreground
+                match_ansi: "\x1B[1;31m",
+                // green, red and yellow foreground
+                added_ansi: "\x1B[32m",
+                removed_ansi: "\x1B[31m",
+                changed_ansi: "\x1B[33m",
+                // yellow foreground
+                note_ansi: "\x1B[33m",
+                reset_ansi: "\x1B[0m",
+            },
+            false => Self {
+                dir_ansi: "",
+                match_ansi: "",
+                added_ansi: "",
+                removed_ansi: "",
+                changed_ansi: "",
+                note_ansi: "",
+                reset_ansi: "",
+            },
+        }
+    }
+
+    /// Print a matching file path with optional color highlighting, followed
+    /// by notes about it: `/home/me/project/bin/make (cwd)`.
+    pub fn print(
+        &self,
+        output: &mut impl Write,
+        dir: &str,
+        file: &str,
+        range: MatchRange,
+        notes: &[String],
+    ) {
+        self.write_path(output, dir, file, range);
+        let _ = write!(output, "{}", self.reset_ansi);
+        self.write_notes(output, notes);
+        let _ = writeln!(output);
+    }
+
+    /// Print a match like `print`, followed by the description of the
+    /// command, if any: `/usr/bin/ls - list directory contents`.
+    pub fn print_described(
+        &self,
+        output: &mut impl Write,
+        m: &Match,
+        notes: &[String],
+        description: Option<&str>,
+    ) {
+        self.write_path(output, &m.dir, &m.file_name, m.range);
+        let _ = write!(output, "{}", self.reset_ansi);
+        self.write_notes(output, notes);
+        if let Some(description) = description {
+            let _ = write!(output, " - {}", description);
+        }
+        let _ = writeln!(output);
+    }
+
+    /// Print the PATH index of a result and, if its name appears more than
+    /// once, its rank among the copies: `[3] #1 of 3 `.
+    pub fn print_rank(
+        &self,
+        output: &mut impl Write,
+        dir_index: usize,
+        (rank, total): (usize, usize),
+    ) {
+        let _ = write!(output, "{}[{}] ", self.dir_ansi, dir_index);
+        if total > 1 {
+            let _ = write!(output, "#{} of {} ", rank, total);
+        }
+        let _ = write!(output, "{}", self.reset_ansi);
+    }
+
+    /// Print a broken entry followed by its symlink chain and the reason it
+    /// is broken, e.g. `/bin/foo -> /opt/foo: target does not exist`.
+    pub fn print_broken(&self, output: &mut impl Write, m: &Match, breakage: &Breakage) {
+        self.write_path(output, &m.dir, &m.file_name, m.range);
+        for link in breakage.chain().iter().skip(1) {
+            let _ = write!(output, " -> {}", link.display());
+        }
+        let _ = writeln!(output, ": {}{}", breakage, self.reset_ansi);
+    }
+
+    /// Print an executable that cannot run and why, e.g.
+    /// `/opt/venv/bin/pip: interpreter /opt/venv/bin/python3 does not exist`.
+    pub fn print_problems(&self, output: &mut impl Write, m: &Match, problems: &[impl Display]) {
+        self.write_path(output, &m.dir, &m.file_name, m.range);
+        let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+        let _ = writeln!(output, "{}: {}", self.reset_ansi, problems.join(", "));
+    }
+
+    /// Print a privileged executable and what it runs with, e.g.
+    /// `/usr/bin/ping: cap_net_raw+ep`.
+    pub fn print_privileged(&self, output: &mut impl Write, m: &Match, privileges: &Privileges) {
+        self.write_path(output, &m.dir, &m.file_name, m.range);
+        let _ = writeln!(
+            output,
+            "{}: {}{}{}",
+            self.reset_ansi, self.note_ansi, privileges, self.reset_ansi
+        );
+    }
+
+    /// Print how long listing a directory took and how it ended, e.g.
+    /// `   12.3ms  /usr/bin: 1532 files`.
+    pub fn print_timing(&self, output: &mut impl Write, timing: &Timing) {
+        let _ = write!(
+            output,
+            "{:>9}  {}{}{}: ",
+            dir_reader::format_duration(timing.elapsed),
+            self.dir_ansi,
+            timing.dir.display(),
+            self.reset_ansi
+        );
+        let _ = match &timing.outcome {
+            Outcome::Listed(count) => writeln!(output, "{} files", count),
+            Outcome::Failed(err) => writeln!(output, "{}", err),
+            Outcome::TimedOut => {
+                writeln!(output, "{}timed out{}", self.removed_ansi, self.reset_ansi)
+            }
+        };
+    }
+
+    /// Print a change in command resolution, changelog-style:
+    /// `~ python3: /usr/bin/python3 -> /opt/venv/bin/python3`.
+    pub fn print_change(&self, output: &mut impl Write, change: &Change) {
+        let _ = match change {
+            Change::Added { name, new } => writeln!(
+                output,
+                "{}+ {}{}: {}",
+                self.added_ansi,
+                name,
+                self.reset_ansi,
+                new.display()
+            ),
+            Change::Removed { name, old } => writeln!(
+                output,
+                "{}- {}{}: {}",
+                self.removed_ansi,
+                name,
+                self.reset_ansi,
+                old.display()
+            ),
+            Change::Changed { name, old, new } => writeln!(
+                output,
+                "{}~ {}{}: {} -> {}",
+                self.changed_ansi,
+                name,
+                self.reset_ansi,
+                old.display(),
+                new.display()
+            ),
+        };
+    }
+
+    /// Print a command from an added directory that an existing command
+    /// shadows: `= make: /opt/vendor/bin/make shadowed by /usr/bin/make`.
+    pub fn print_shadowed(&self, output: &mut impl Write, shadowed: &Shadowed) {
+        let _ = writeln!(
+            output,
+            "{}= {}: {} shadowed by {}{}",
+            self.dir_ansi,
+            shadowed.name,
+            shadowed.path.display(),
+            shadowed.by.display(),
+            self.reset_ansi
+        );
+    }
+
+    /// Print a problem found by `doctor`:
+    /// `warning: [3] /opt/tool/bin: does not exist`.
+    pub fn print_finding(&self, output: &mut impl Write, entry: &PathEntry, finding: &Finding) {
+        let severity = finding.problem.severity();
+        // show the entry as written; an empty entry would be invisible
+        let raw = match entry.raw.is_empty() {
+            true => "''".into(),
+            false => entry.raw.to_string_lossy(),
+        };
+        let _ = writeln!(
+            output,
+            "{}{}{}: [{}] {}: {}",
+            self.severity_ansi(severity),
+            severity,
+            self.reset_ansi,
+            finding.index,
+            raw,
+            finding.problem
+        );
+    }
+
+    /// Print an `audit` finding and its hint:
+    /// `error: [0] /tmp/bin: writable by everyone, searched before /usr/bin`
+    /// followed by `    hint: chmod o-w /tmp/bin`.
+    pub fn print_audit_finding(&self, output: &mut impl Write, finding: &audit::Finding) {
+        let severity = finding.severity();
+        let _ = writeln!(
+            output,
+            "{}{}{}: [{}] {}",
+            self.severity_ansi(severity),
+            severity,
+            self.reset_ansi,
+            finding.index,
+            finding
+        );
+        let _ = writeln!(
+            output,
+            "    {}hint: {}{}",
+            self.dir_ansi,
+            finding.hint(),
+            self.reset_ansi
+        );
+    }
+
+    fn severity_ansi(&self, severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => self.removed_ansi,
+            Severity::Warning => self.changed_ansi,
+            Severity::Note => self.dir_ansi,
+        }
+    }
+
+    /// Print the header line of a directory group: `/usr/bin:`, or
+    /// `/home/me/project: (cwd)` with a note.
+    pub fn print_group_header(&self, output: &mut impl Write, dir: &str, notes: &[String]) {
+        let _ = write!(output, "{}{}:{}", self.dir_ansi, dir, self.reset_ansi);
+        self.write_notes(output, notes);
+        let _ = writeln!(output);
+    }
+
+    /// Print the file names of a directory group, each followed by its
+    /// `notes` (`notes[i]` for `matches[i]`).
+    ///
+    /// Names are printed one per line, or laid out in columns, ls-style, when
+    /// the terminal `width` is known.
+    pub fn print_group(
+        &self,
+        output: &mut impl Write,
+        matches: &[Match],
+        notes: &[Vec<String>],
+        width: Option<usize>,
+    ) {
+        let Some(width) = width else {
+            for (m, notes) in matches.iter().zip(notes) {
+                self.write_file_name(output, &m.file_name, m.range);
+                self.write_notes(output, notes);
+                let _ = writeln!(output, "{}", self.reset_ansi);
+            }
+            return;
+        };
+
+        // ` (note)` for each note
+        let lens: Vec<usize> = matches
+            .iter()
+            .zip(notes)
+            .map(|(m, notes)| {
+                let notes_len: usize = notes.iter().map(|note| note.chars().count() + 3).sum();
+                m.file_name.chars().count() + notes_len
+            })
+            .collect();
+        let col_widths = column_layout(&lens, width);
+        let rows = matches.len().div_ceil(col_widths.len().max(1));
+
+        for row in 0..rows {
+            for (col, col_width) in col_widths.iter().enumerate() {
+                let i = col * rows + row;
+                let Some(m) = matches.get(i) else {
+                    break;
+                };
+                self.write_file_name(output, &m.file_name, m.range);
+                self.write_notes(output, &notes[i]);
+                // pad all but the last column in the row
+                if col + 1 < col_widths.len() && i + rows < matches.len() {
+                    let _ = write!(output, "{:pad$}", "", pad = col_width - lens[i]);
+                }
+            }
+            let _ = writeln!(output, "{}", self.reset_ansi);
+        }
+    }
+
+    /// Write ` (note)` for each note.
+    fn write_notes(&self, output: &mut impl Write, notes: &[String]) {
+        for note in notes {
+            let _ = write!(output, " {}({}){}", self.note_ansi, note, self.reset_ansi);
+        }
+    }
+
+    /// Write a file path without the trailing newline.
+    fn write_path(&self, output: &mut impl Write, dir: &str, file: &str, range: MatchRange) {
+        // write directory with dimmed color
+        let _ = write!(
+            output,
+            "{}{}{}{}",
+            self.dir_ansi, dir, MAIN_SEPARATOR, self.reset_ansi
+        );
+        self.write_file_name(output, file, range);
+    }
+
+    /// Write a filename with match range highlighting.
+    fn write_file_name(&self, output: &mut impl Write, file: &str, range: MatchRange) {
+        let filename = file.as_bytes();
+        match range {
+            MatchRange::None => {
+                let _ = output.write(filename);
+            }
+            MatchRange::Range(start, end) => {
+                let _ = output.write_all(&filename[..start]);
+                let _ = write!(output, "{}", self.match_ansi);
+                let _ = output.write_all(&filename[start..end]);
+                let _ = write!(output, "{}", self.reset_ansi);
+                let _ = output.write_all(&filename[end..]);
+            }
+        }
+    }
+}
+
+/// Spacing between columns in grouped output
+const COLUMN_GAP: usize = 2;
+
+/// Lay out items of the given display lengths in columns, ls-style.
+///
+/// Items fill columns top to bottom, then left to right. Returns the width
+/// of each column, including the gap after it, using as many columns as fit
+/// in `width`.
+fn column_layout(lens: &[usize], width: usize) -> Vec<usize> {
+    let widths_for = |rows: usize| -> Vec<usize> {
+        lens.chunks(rows)
+            .map(|col| col.iter().max().copied().unwrap_or(0) + COLUMN_GAP)
+            .collect()
+    };
+
+    for rows in 1..lens.len() {
+        let widths = widths_for(rows);
+        // the last column doesn't need its gap
+        if widths.iter().sum::<usize>() - COLUMN_GAP <= width {
+            return widths;
+        }
+    }
+
+    widths_for(lens.len().max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ========================================
+    // FormattedOutput tests
+    // ========================================
+
+    mod formatted_output {
+        use super::*;
+        use std::path::MAIN_SEPARATOR as SEP;
+
+        // ANSI escape code constants for test assertions
+        const DIM: &str = "\x1B[2m";
+        const BOLD_RED: &str = "\x1B[1;31m";
+        const RESET: &str = "\x1B[0m";
+
+        // --- Construction tests ---
+
+        #[test]
+        fn new_with_color_enabled() {
+            let output = FormattedOutput::new(true);
+            assert_eq!(output.dir_ansi, DIM);
+            assert_eq!(output.match_ansi, BOLD_RED);
+            assert_eq!(output.reset_ansi, RESET);
+        }
+
+        #[test]
+        fn new_with_color_disabled() {
+            let output = FormattedOutput::new(false);
+            assert_eq!(output.dir_ansi, "");
+            assert_eq!(output.match_ansi, "");
+            assert_eq!(output.reset_ansi, "");
+        }
+
+        // --- Print output tests (no color) ---
+
+        #[test]
+        fn print_no_color_no_match_range() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "ls", MatchRange::None, &[]);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/usr/bin{SEP}ls\n")
+            );
+        }
+
+        #[test]
+        fn print_no_color_with_match_range() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(0, 4), &[]);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/usr/bin{SEP}grep\n")
+            );
+        }
+
+        #[test]
+        fn print_with_note_no_color() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            let notes = ["cwd".to_string(), "not executable by deploy".to_string()];
+            output.print(&mut buf, "/work", "make", MatchRange::None, &notes);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/work{SEP}make (cwd) (not executable by deploy)\n")
+            );
+        }
+
+        // --- Print output tests (with color) ---
+
+        #[test]
+        fn print_color_no_match_range() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "ls", MatchRange::None, &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // Directory should be dimmed, filename plain, ends with reset
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}ls{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_with_note_color() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(
+                &mut buf,
+                "/work",
+                "ls",
+                MatchRange::None,
+                &["relative".to_string()],
+            );
+            let result = String::from_utf8(buf).unwrap();
+            let expected = format!("{DIM}/work{SEP}{RESET}ls{RESET} \x1B[33m(relative){RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_color_match_at_start() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(0, 2), &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // "gr" highlighted, "ep" plain
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}{BOLD_RED}gr{RESET}ep{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_color_match_at_end() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "grep", MatchRange::Range(2, 4), &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // "gr" plain, "ep" highlighted
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}gr{BOLD_RED}ep{RESET}{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_color_match_in_middle() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "cargo", MatchRange::Range(1, 3), &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // "c" plain, "ar" highlighted, "go" plain
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}c{BOLD_RED}ar{RESET}go{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_color_full_filename_match() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "ls", MatchRange::Range(0, 2), &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // Entire "ls" highlighted
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}{BOLD_RED}ls{RESET}{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        // --- Rank prefix tests ---
+
+        #[test]
+        fn print_rank_unique_name() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_rank(&mut buf, 3, (1, 1));
+            assert_eq!(String::from_utf8(buf).unwrap(), "[3] ");
+        }
+
+        #[test]
+        fn print_rank_shadowed_name() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_rank(&mut buf, 17, (2, 3));
+            assert_eq!(String::from_utf8(buf).unwrap(), "[17] #2 of 3 ");
+        }
+
+        #[test]
+        fn print_rank_color() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print_rank(&mut buf, 0, (1, 2));
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("{DIM}[0] #1 of 2 {RESET}")
+            );
+        }
+
+        // --- Diff tests ---
+
+        #[test]
+        fn print_change_no_color() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_change(
+                &mut buf,
+                &Change::Changed {
+                    name: "python3".to_string(),
+                    old: "/usr/bin/python3".into(),
+                    new: "/opt/venv/bin/python3".into(),
+                },
+            );
+            output.print_change(
+                &mut buf,
+                &Change::Removed {
+                    name: "ls".to_string(),
+                    old: "/usr/bin/ls".into(),
+                },
+            );
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "~ python3: /usr/bin/python3 -> /opt/venv/bin/python3\n- ls: /usr/bin/ls\n"
+            );
+        }
+
+        #[test]
+        fn print_change_color() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print_change(
+                &mut buf,
+                &Change::Added {
+                    name: "activate".to_string(),
+                    new: "/opt/venv/bin/activate".into(),
+                },
+            );
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("\x1B[32m+ activate{RESET}: /opt/venv/bin/activate\n")
+            );
+        }
+
+        #[test]
+        fn print_shadowed_no_color() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_shadowed(
+                &mut buf,
+                &Shadowed {
+                    name: "make".to_string(),
+                    path: "/opt/vendor/bin/make".into(),
+                    by: "/usr/bin/make".into(),
+                },
+            );
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "= make: /opt/vendor/bin/make shadowed by /usr/bin/make\n"
+            );
+        }
+
+        #[test]
+        fn print_privileged_no_color() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            let m = Match {
+                dir_index: 0,
+                dir: "/usr/bin".to_string(),
+                file_name: "ping".to_string(),
+                range: MatchRange::None,
+            };
+            let privileges = Privileges {
+                capabilities: Some("cap_net_raw+ep".to_string()),
+                ..Privileges::default()
+            };
+            output.print_privileged(&mut buf, &m, &privileges);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/usr/bin{SEP}ping: cap_net_raw+ep\n")
+            );
+        }
+
+        #[test]
+        fn print_described_no_color() {
+            let output = FormattedOutput::new(false);
+            let m = Match {
+                dir_index: 0,
+                dir: "/usr/bin".to_string(),
+                file_name: "gzip".to_string(),
+                range: MatchRange::Range(1, 3),
+            };
+            let mut buf = Vec::new();
+            output.print_described(&mut buf, &m, &["cwd".to_string()], Some("compress files"));
+            output.print_described(&mut buf, &m, &[], None);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/usr/bin{SEP}gzip (cwd) - compress files\n/usr/bin{SEP}gzip\n")
+            );
+        }
+
+        #[test]
+        fn print_timing_no_color() {
+            use std::path::PathBuf;
+            use std::time::Duration;
+
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            for (dir, outcome) in [
+                ("/usr/bin", Outcome::Listed(1532)),
+                ("/net", Outcome::TimedOut),
+            ] {
+                let timing = Timing {
+                    dir: PathBuf::from(dir),
+                    elapsed: Duration::from_micros(12_345),
+                    outcome,
+                };
+                output.print_timing(&mut buf, &timing);
+            }
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "   12.3ms  /usr/bin: 1532 files\n   12.3ms  /net: timed out\n"
+            );
+        }
+
+        #[test]
+        fn print_finding_no_color() {
+            use crate::doctor::Problem;
+            use crate::path_entry::EntryKind;
+
+            let output = FormattedOutput::new(false);
+            let entry = |raw: &str, kind| PathEntry {
+                raw: raw.into(),
+                dir: "/work".into(),
+                kind,
+            };
+            let mut buf = Vec::new();
+            output.print_finding(
+                &mut buf,
+                &entry("/opt/bin", EntryKind::Absolute),
+                &Finding {
+                    index: 3,
+                    problem: Problem::Missing,
+                },
+            );
+            output.print_finding(
+                &mut buf,
+                &entry("", EntryKind::Empty),
+                &Finding {
+                    index: 4,
+                    problem: Problem::Empty,
+                },
+            );
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "warning: [3] /opt/bin: does not exist\n\
+                 error: [4] '': empty entry means the current directory\n"
+            );
+        }
+
+        // --- Edge case tests ---
+
+        #[test]
+        fn print_empty_filename() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "", MatchRange::None, &[]);
+            assert_eq!(String::from_utf8(buf).unwrap(), format!("/usr/bin{SEP}\n"));
+        }
+
+        #[test]
+        fn print_filename_with_ansi_escape_in_name() {
+            // Filenames could theoretically contain ANSI sequences
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            let filename = "file\x1B[31mred";
+            output.print(&mut buf, "/tmp", filename, MatchRange::None, &[]);
+            // Should pass through unchanged (no sanitization)
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                format!("/tmp{SEP}file\x1B[31mred\n")
+            );
+        }
+
+        #[test]
+        fn print_empty_directory() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "", "ls", MatchRange::None, &[]);
+            assert_eq!(String::from_utf8(buf).unwrap(), format!("{SEP}ls\n"));
+        }
+
+        // --- Match range boundary tests ---
+
+        #[test]
+        fn print_match_range_single_byte() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            output.print(&mut buf, "/usr/bin", "abc", MatchRange::Range(1, 2), &[]);
+            let result = String::from_utf8(buf).unwrap();
+            // "a" plain, "b" highlighted, "c" plain
+            let expected = format!("{DIM}/usr/bin{SEP}{RESET}a{BOLD_RED}b{RESET}c{RESET}\n");
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn print_match_range_with_multibyte_utf8() {
+            // Test that byte-based slicing works correctly with UTF-8
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            // "café" - the 'é' is 2 bytes (0xc3 0xa9)
+            let filename = "café";
+            // Match "af" which spans bytes 1-3 (the 'a' and first byte of 'é')
+            // This tests that we're doing byte slicing, not character slicing
+            output.print(&mut buf, "/tmp", filename, MatchRange::Range(1, 3), &[]);
+            // The output will slice at byte boundaries
+            // "c" then highlighted "af" (bytes 1-3) then "é" remainder
+            assert!(!buf.is_empty()); // Just verify it doesn't panic
+        }
+    }
+
+    // ========================================
+    // Grouped output tests
+    // ========================================
+
+    mod grouped_output {
+        use super::*;
+
+        fn m(file_name: &str) -> Match {
+            Match {
+                dir_index: 0,
+                dir: "/usr/bin".to_string(),
+                file_name: file_name.to_string(),
+                range: MatchRange::None,
+            }
+        }
+
+        #[test]
+        fn column_layout_everything_fits_in_one_row() {
+            assert_eq!(column_layout(&[2, 3, 4], 80), vec![4, 5, 6]);
+        }
+
+        #[test]
+        fn column_layout_wraps_into_rows() {
+            // 2 rows: columns [5, 1], [2, 2] -> widths 7 + 4 - gap = 9
+            assert_eq!(column_layout(&[5, 1, 2, 2], 9), vec![7, 4]);
+        }
+
+        #[test]
+        fn column_layout_single_column_when_too_wide() {
+            assert_eq!(column_layout(&[50, 60], 40), vec![62]);
+        }
+
+        #[test]
+        fn column_layout_empty() {
+            assert!(column_layout(&[], 80).is_empty());
+        }
+
+        #[test]
+        fn print_group_header_no_color() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_group_header(&mut buf, "/usr/bin", &[]);
+            assert_eq!(String::from_utf8(buf).unwrap(), "/usr/bin:\n");
+        }
+
+        #[test]
+        fn print_group_header_with_note() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_group_header(&mut buf, "/work", &["cwd".to_string()]);
+            assert_eq!(String::from_utf8(buf).unwrap(), "/work: (cwd)\n");
+        }
+
+        #[test]
+        fn print_group_one_per_line_without_width() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            output.print_group(&mut buf, &[m("ls"), m("cat")], &[vec![], vec![]], None);
+            assert_eq!(String::from_utf8(buf).unwrap(), "ls\ncat\n");
+        }
+
+        #[test]
+        fn print_group_columns() {
+            let output = FormattedOutput::new(false);
+            let mut buf = Vec::new();
+            let matches = [m("a"), m("bbbbb"), m("cc"), m("d"), m("e")];
+            output.print_group(&mut buf, &matches, &vec![vec![]; 5], Some(12));
+            // 2 rows: [a, bbbbb] [cc, d] [e]
+            assert_eq!(String::from_utf8(buf).unwrap(), "a      cc  e\nbbbbb  d\n");
+        }
+
+        #[test]
+        fn print_group_with_notes() {
+            let output = FormattedOutput::new(false);
+            let notes = [vec![], vec!["not executable by bob".to_string()]];
+            let mut buf = Vec::new();
+            output.print_group(&mut buf, &[m("ls"), m("cat")], &notes, None);
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "ls\ncat (not executable by bob)\n"
+            );
+
+            // notes count towards the column widths
+            let notes = [vec!["x".to_string()], vec![], vec![], vec![]];
+            let mut buf = Vec::new();
+            let matches = [m("a"), m("b"), m("c"), m("d")];
+            output.print_group(&mut buf, &matches, &notes, Some(10));
+            assert_eq!(String::from_utf8(buf).unwrap(), "a (x)  c\nb      d\n");
+        }
+
+        #[test]
+        fn print_group_highlights_match() {
+            let output = FormattedOutput::new(true);
+            let mut buf = Vec::new();
+            let mut grep = m("grep");
+            grep.range = MatchRange::Range(0, 2);
+            output.print_group(&mut buf, &[grep], &[vec![]], Some(80));
+            assert_eq!(
+                String::from_utf8(buf).unwrap(),
+                "\x1B[1;31mgr\x1B[0mep\x1B[0m\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Resolving the entries of a path list to directories.
+//!
+//! Most PATH entries are absolute directories, but POSIX gives an empty entry
+//! (`::`, or a leading or trailing `:`) the meaning "the current directory",
+//! and relative entries are looked up relative to it. bash additionally
+//! expands a leading `~` in PATH entries when searching for commands. These
+//! entries depend on where the shell happens to be, so results from them are
+//! marked in the output.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::users;
+
+/// How an entry of the path list was written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    Absolute,
+    /// Empty entry, meaning the current directory
+    Empty,
+    /// Relative entry, resolved against the current directory
+    Relative,
+    /// Entry starting with `~`, expanded like bash does
+    Tilde,
+}
+
+impl EntryKind {
+    /// Note printed after results from entries of this kind.
+    pub fn note(self) -> Option<&'static str> {
+        match self {
+            EntryKind::Absolute => None,
+            EntryKind::Empty => Some("cwd"),
+            EntryKind::Relative => Some("relative"),
+            EntryKind::Tilde => Some("tilde"),
+        }
+    }
+}
+
+/// One entry of a path list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathEntry {
+    /// The entry as written in the path list
+    pub raw: OsString,
+    /// Directory the entry refers to
+    pub dir: PathBuf,
+    pub kind: EntryKind,
+}
+
+/// What entries that are not absolute are resolved against.
+pub struct Context {
+    /// Current directory of the shell (or process) whose path list this is.
+    /// `None` if it is unknown, in which case relative entries are left as
+    /// they are.
+    pub cwd: Option<PathBuf>,
+    /// `$HOME`, for expanding `~`
+    pub home: Option<PathBuf>,
+    /// Whether to expand `~` at all. bash only does so for PATH.
+    pub expand_tilde: bool,
+}
+
+impl Context {
+    /// Context of our own process.
+    pub fn current(expand_tilde: bool) -> Self {
+        Context {
+            cwd: env::current_dir().ok(),
+            home: env::var_os("HOME").map(PathBuf::from),
+            expand_tilde,
+        }
+    }
+
+    /// Resolve a single entry.
+    pub fn resolve(&self, raw: &OsStr) -> PathEntry {
+        let path = Path::new(raw);
+        let (dir, kind) = if raw.is_empty() {
+            (self.relative(Path::new("")), EntryKind::Empty)
+        } else if path.is_absolute() {
+            (path.to_path_buf(), EntryKind::Absolute)
+        } else if let Some(dir) = self.expand_tilde(raw) {
+            (dir, EntryKind::Tilde)
+        } else {
+            (self.relative(path), EntryKind::Relative)
+        };
+        PathEntry {
+            raw: raw.to_os_string(),
+            dir,
+            kind,
+        }
+    }
+
+    /// Split a colon-separated path list and resolve each entry.
+    pub fn parse(&self, list: &OsStr) -> Vec<PathEntry> {
+        env::split_paths(list)
+            .map(|raw| self.resolve(raw.as_os_str()))
+            .collect()
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        match &self.cwd {
+            // `components` drops `.` components and trailing separators
+            Some(cwd) => cwd.join(path).components().collect(),
+            None if path.as_os_str().is_empty() => PathBuf::from("."),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Expand `~` or `~user` at the start of `raw`. Returns `None` if `raw`
+    /// does not start with a tilde prefix that bash would expand, in which
+    /// case it is an ordinary relative entry.
+    fn expand_tilde(&self, raw: &OsStr) -> Option<PathBuf> {
+        if !self.expand_tilde {
+            return None;
+        }
+        let bytes = raw.as_bytes().strip_prefix(b"~")?;
+        let (user, rest) = match bytes.iter().position(|&b| b == b'/') {
+            Some(slash) => bytes.split_at(slash),
+            None => (bytes, &b""[..]),
+        };
+        let home = match user {
+            b"" => self.home.clone()?,
+            user => users::by_name(std::str::from_utf8(user).ok()?)?.home,
+        };
+        let rest = Path::new(OsStr::from_bytes(rest));
+        Some(match rest.strip_prefix("/") {
+            Ok(rest) if !rest.as_os_str().is_empty() => home.join(rest),
+            _ => home,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            cwd: Some(PathBuf::from("/work")),
+            home: Some(PathBuf::from("/home/me")),
+            expand_tilde: true,
+        }
+    }
+
+    fn dirs(entries: &[PathEntry]) -> Vec<(&Path, EntryKind)> {
+        entries.iter().map(|e| (e.dir.as_path(), e.kind)).collect()
+    }
+
+    #[test]
+    fn absolute_entries_are_kept() {
+        let entries = context().parse(OsStr::new("/usr/bin:/bin/"));
+        assert_eq!(
+            dirs(&entries),
+            [
+                (Path::new("/usr/bin"), EntryKind::Absolute),
+                (Path::new("/bin/"), EntryKind::Absolute),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_entries_are_the_current_directory() {
+        for list in ["::/bin", ":/bin", "/bin:"] {
+            let entries = context().parse(OsStr::new(list));
+            let empty: Vec<_> = entries
+                .iter()
+                .filter(|e| e.kind == EntryKind::Empty)
+                .collect();
+            assert_eq!(
+                empty.len(),
+                if list == "::/bin" { 2 } else { 1 },
+                "{}",
+                list
+            );
+            assert!(empty.iter().all(|e| e.dir == Path::new("/work")));
+        }
+        // an empty list is a single empty entry
+        assert_eq!(
+            dirs(&context().parse(OsStr::new(""))),
+            [(Path::new("/work"), EntryKind::Empty)]
+        );
+    }
+
+    #[test]
+    fn relative_entries_are_joined_with_the_current_directory() {
+        let entries = context().parse(OsStr::new("bin:./node_modules/.bin:.:../tools"));
+        assert_eq!(
+            dirs(&entries),
+            [
+                (Path::new("/work/bin"), EntryKind::Relative),
+                (Path::new("/work/node_modules/.bin"), EntryKind::Relative),
+                (Path::new("/work"), EntryKind::Relative),
+                (Path::new("/work/../tools"), EntryKind::Relative),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_entries_without_current_directory() {
+        let context = Context {
+            cwd: None,
+            ..context()
+        };
+        assert_eq!(
+            dirs(&context.parse(OsStr::new(":bin"))),
+            [
+                (Path::new("."), EntryKind::Empty),
+                (Path::new("bin"), EntryKind::Relative),
+            ]
+        );
+    }
+
+    #[test]
+    fn tilde_is_expanded() {
+        let entries = context().parse(OsStr::new("~:~/bin:~/"));
+        assert_eq!(
+            dirs(&entries),
+            [
+                (Path::new("/home/me"), EntryKind::Tilde),
+                (Path::new("/home/me/bin"), EntryKind::Tilde),
+                (Path::new("/home/me"), EntryKind::Tilde),
+            ]
+        );
+        assert_eq!(entries[1].raw, "~/bin");
+    }
+
+    #[test]
+    fn tilde_user_is_expanded() {
+        let root_home = users::by_name("root").unwrap().home;
+        let entries = context().parse(OsStr::new("~root/bin:~nosuchuser/bin"));
+        assert_eq!(
+            dirs(&entries),
+            [
+                (root_home.join("bin").as_path(), EntryKind::Tilde),
+                (Path::new("/work/~nosuchuser/bin"), EntryKind::Relative),
+            ]
+        );
+    }
+
+    #[test]
+    fn tilde_only_expanded_at_start() {
+        let entries = context().parse(OsStr::new("bin/~"));
+        assert_eq!(
+            dirs(&entries),
+            [(Path::new("/work/bin/~"), EntryKind::Relative)]
+        );
+    }
+
+    #[test]
+    fn unexpandable_tilde_is_relative() {
+        let no_home = Context {
+            home: None,
+            ..context()
+        };
+        assert_eq!(
+            dirs(&no_home.parse(OsStr::new("~/bin"))),
+            [(Path::new("/work/~/bin"), EntryKind::Relative)]
+        );
+
+        let no_expansion = Context {
+            expand_tilde: false,
+            ..context()
+        };
+        assert_eq!(
+            dirs(&no_expansion.parse(OsStr::new("~/bin"))),
+            [(Path::new("/work/~/bin"), EntryKind::Relative)]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Colon-separated path-list environment variables.
+//!
+//! pathsearch searches PATH by default, but the same PATH-order lookup
+//! applies to other path lists, such as `LD_LIBRARY_PATH` or `MANPATH`.
+//! The presets below know what each variable defaults to when unset.
+
+use std::env;
+use std::ffi::OsString;
+
+struct Preset {
+    name: &'static str,
+    /// Value used when the variable is unset
+    default: &'static str,
+    /// Whether an empty value also means "use the default". For most
+    /// variables an empty value is a single empty entry instead.
+    empty_is_unset: bool,
+}
+
+const PRESETS: &[Preset] = &[
+    // what `execvp` falls back to (`_CS_PATH` on glibc)
+    Preset {
+        name: "PATH",
+        default: "/bin:/usr/bin",
+        empty_is_unset: false,
+    },
+    Preset {
+        name: "MANPATH",
+        default: "/usr/local/share/man:/usr/share/man",
+        empty_is_unset: false,
+    },
+    // the dynamic loader's trusted directories
+    Preset {
+        name: "LD_LIBRARY_PATH",
+        default: "/lib:/usr/lib:/lib64:/usr/lib64",
+        empty_is_unset: false,
+    },
+    // `cd` only looks in the current directory
+    Preset {
+        name: "CDPATH",
+        default: ".",
+        empty_is_unset: false,
+    },
+    // pkg-config's built-in search path
+    Preset {
+        name: "PKG_CONFIG_PATH",
+        default: "/usr/local/lib/pkgconfig:/usr/local/share/pkgconfig:/usr/lib/pkgconfig:/usr/share/pkgconfig",
+        empty_is_unset: false,
+    },
+    // XDG Base Directory Specification
+    Preset {
+        name: "XDG_DATA_DIRS",
+        default: "/usr/local/share:/usr/share",
+        empty_is_unset: true,
+    },
+    Preset {
+        name: "XDG_CONFIG_DIRS",
+        default: "/etc/xdg",
+        empty_is_unset: true,
+    },
+];
+
+fn preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+/// Value the path-list variable `name` defaults to when unset, if known.
+pub fn default_value(name: &str) -> Option<&'static str> {
+    preset(name).map(|preset| preset.default)
+}
+
+/// Resolve the value of the path-list variable `name`, given its value in
+/// the environment being inspected. Falls back to the preset default (with
+/// a note on stderr) when the variable is unset.
+pub fn resolve_value(name: &str, value: Option<OsString>) -> Result<OsString, String> {
+    let preset = preset(name);
+    match value {
+        Some(value) if !value.is_empty() || !preset.is_some_and(|p| p.empty_is_unset) => Ok(value),
+        _ => match preset {
+            Some(preset) => {
+                eprintln!(
+                    "{} is not set, searching the default path {}",
+                    name, preset.default
+                );
+                Ok(OsString::from(preset.default))
+            }
+            None => Err(format!("{} is not set and has no default", name)),
+        },
+    }
+}
+
+/// Value of the path-list variable `name` in our own environment (see
+/// `resolve_value`).
+pub fn value(name: &str) -> Result<OsString, String> {
+    resolve_value(name, env::var_os(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_of_known_variables() {
+        assert_eq!(preset("PATH").unwrap().default, "/bin:/usr/bin");
+        assert_eq!(preset("CDPATH").unwrap().default, ".");
+        assert_eq!(
+            preset("XDG_DATA_DIRS").unwrap().default,
+            "/usr/local/share:/usr/share"
+        );
+    }
+
+    #[test]
+    fn resolve_value_keeps_set_value() {
+        let value = resolve_value("PATH", Some("/usr/bin".into()));
+        assert_eq!(value, Ok("/usr/bin".into()));
+    }
+
+    #[test]
+    fn resolve_value_uses_default_when_unset() {
+        assert_eq!(resolve_value("PATH", None), Ok("/bin:/usr/bin".into()));
+        assert!(resolve_value("PYTHONPATH", None).is_err());
+    }
+
+    #[test]
+    fn resolve_value_empty_value() {
+        // an empty PATH is a single empty entry, i.e. the current directory
+        assert_eq!(resolve_value("PATH", Some("".into())), Ok("".into()));
+        // but the XDG spec treats empty like unset
+        assert_eq!(
+            resolve_value("XDG_CONFIG_DIRS", Some("".into())),
+            Ok("/etc/xdg".into())
+        );
+    }
+
+    #[test]
+    fn no_preset_for_unknown_variables() {
+        assert!(preset("PYTHONPATH").is_none());
+        assert!(preset("path").is_none());
+    }
+}
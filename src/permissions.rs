@@ -0,0 +1,349 @@
+//! Permission checks for a given user.
+//!
+//! Decides what a user may do with a file from its mode bits, ownership and
+//! POSIX ACL, the way the kernel does, without needing to be that user.
+
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::sys;
+use crate::users::{self, User};
+
+/// Permission bits, as in one class of a file mode
+const EXECUTE: u32 = 0o1;
+
+/// User and group IDs that permissions are checked against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    /// User name, for messages
+    pub name: String,
+    pub uid: u32,
+    /// Primary and supplementary groups
+    pub gids: Vec<u32>,
+}
+
+impl Credentials {
+    /// Effective credentials of this process.
+    pub fn current() -> Self {
+        let uid = sys::effective_uid();
+        let mut gids = vec![sys::effective_gid()];
+        for gid in sys::supplementary_groups() {
+            if !gids.contains(&gid) {
+                gids.push(gid);
+            }
+        }
+        Credentials {
+            name: users::by_uid(uid).map_or(uid.to_string(), |user| user.name),
+            uid,
+            gids,
+        }
+    }
+
+    /// Credentials `user` gets when logging in: their primary group and
+    /// every group that lists them as a member.
+    pub fn of_user(user: &User) -> Self {
+        Credentials {
+            name: user.name.clone(),
+            uid: user.uid,
+            gids: users::group_ids(user),
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Whether these credentials have permission `perm` (a combination of
+    /// read `0o4`, write `0o2` and execute `0o1`) on `path`, which is described by
+    /// `meta`.
+    ///
+    /// Root may read and write anything, and execute anything that has an
+    /// execute bit set (or is a directory).
+    fn allows(&self, path: &Path, meta: &Metadata, perm: u32) -> bool {
+        if self.is_root() {
+            return perm & EXECUTE == 0 || meta.is_dir() || meta.mode() & 0o111 != 0;
+        }
+        match sys::xattr(path, "system.posix_acl_access") {
+            Ok(Some(value)) => match Acl::parse(&value) {
+                Some(acl) => acl.allows(self, meta.uid(), meta.gid(), perm),
+                None => self.mode_allows(meta, perm),
+            },
+            _ => self.mode_allows(meta, perm),
+        }
+    }
+
+    /// Permission check from the mode bits alone. Only one class of bits
+    /// applies: the owner's if the user owns the file, else the group's if
+    /// the user is in its group, else everyone else's.
+    fn mode_allows(&self, meta: &Metadata, perm: u32) -> bool {
+        let mode = meta.mode();
+        let class = if meta.uid() == self.uid {
+            mode >> 6
+        } else if self.gids.contains(&meta.gid()) {
+            mode >> 3
+        } else {
+            mode
+        };
+        class & perm == perm
+    }
+
+    /// Whether these credentials can run `path` as a command: it must be a
+    /// regular file they may execute, and they must be able to search every
+    /// directory on the way to it.
+    pub fn can_execute(&self, path: &Path) -> bool {
+        let Ok(meta) = fs::metadata(path) else {
+            return false;
+        };
+        if !meta.is_file() || !self.allows(path, &meta, EXECUTE) {
+            return false;
+        }
+        // both the directories leading to the symlink and to its target
+        let canonical = fs::canonicalize(path).ok();
+        let dirs = path
+            .ancestors()
+            .skip(1)
+            .chain(canonical.iter().flat_map(|path| path.ancestors().skip(1)));
+        dirs.filter(|dir| !dir.as_os_str().is_empty())
+            .all(|dir| fs::metadata(dir).is_ok_and(|meta| self.allows(dir, &meta, EXECUTE)))
+    }
+}
+
+/// Parse a comma-separated list of group names or IDs.
+pub fn parse_groups(list: &str) -> Result<Vec<u32>, String> {
+    list.split(',')
+        .map(|group| {
+            group
+                .parse()
+                .ok()
+                .or_else(|| users::group_id(group))
+                .ok_or(format!("No group named '{}'", group))
+        })
+        .collect()
+}
+
+/// A POSIX access ACL (`system.posix_acl_access`).
+#[derive(Debug, PartialEq)]
+struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+struct AclEntry {
+    tag: u16,
+    perm: u32,
+    id: u32,
+}
+
+const ACL_XATTR_VERSION: u32 = 2;
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+impl Acl {
+    /// Parse the xattr: a little-endian `u32` version followed by 8-byte
+    /// entries of `u16` tag, `u16` permissions and `u32` ID.
+    fn parse(value: &[u8]) -> Option<Acl> {
+        let (version, entries) = value.split_first_chunk::<4>()?;
+        if u32::from_le_bytes(*version) != ACL_XATTR_VERSION || entries.len() % 8 != 0 {
+            return None;
+        }
+        let entries = entries
+            .chunks_exact(8)
+            .map(|entry| AclEntry {
+                tag: u16::from_le_bytes([entry[0], entry[1]]),
+                perm: u32::from(u16::from_le_bytes([entry[2], entry[3]])),
+                id: u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]),
+            })
+            .collect();
+        Some(Acl { entries })
+    }
+
+    fn perm(&self, tag: u16) -> Option<u32> {
+        self.entries.iter().find(|e| e.tag == tag).map(|e| e.perm)
+    }
+
+    /// The access check algorithm from acl(5).
+    fn allows(&self, credentials: &Credentials, owner: u32, group: u32, perm: u32) -> bool {
+        let granted = |entry_perm: u32| entry_perm & perm == perm;
+        let mask = self.perm(ACL_MASK).unwrap_or(0o7);
+
+        if credentials.uid == owner {
+            return self.perm(ACL_USER_OBJ).is_some_and(granted);
+        }
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|e| e.tag == ACL_USER && e.id == credentials.uid)
+        {
+            return granted(entry.perm & mask);
+        }
+
+        // any matching group entry that grants the permission will do, but
+        // matching one at all rules out the "other" entry
+        let mut group_matched = false;
+        for entry in &self.entries {
+            let matches = match entry.tag {
+                ACL_GROUP_OBJ => credentials.gids.contains(&group),
+                ACL_GROUP => credentials.gids.contains(&entry.id),
+                _ => false,
+            };
+            if matches {
+                if granted(entry.perm & mask) {
+                    return true;
+                }
+                group_matched = true;
+            }
+        }
+        if group_matched {
+            return false;
+        }
+
+        self.perm(ACL_OTHER).is_some_and(granted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    const READ: u32 = 0o4;
+    const WRITE: u32 = 0o2;
+
+    fn credentials(uid: u32, gids: &[u32]) -> Credentials {
+        Credentials {
+            name: "tester".to_string(),
+            uid,
+            gids: gids.to_vec(),
+        }
+    }
+
+    fn with_mode(dir: &TempDir, mode: u32) -> (std::path::PathBuf, Metadata) {
+        let file = dir.touch(&format!("mode-{:o}", mode));
+        fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+        let meta = fs::metadata(&file).unwrap();
+        (file, meta)
+    }
+
+    #[test]
+    fn owner_group_and_other_classes() {
+        let dir = TempDir::new("permissions-classes");
+        let (file, meta) = with_mode(&dir, 0o754);
+        let owner = credentials(meta.uid(), &[]);
+        assert!(owner.allows(&file, &meta, READ | WRITE | EXECUTE));
+
+        let group = credentials(u32::MAX - 1, &[meta.gid()]);
+        assert!(group.allows(&file, &meta, READ | EXECUTE));
+        assert!(!group.allows(&file, &meta, WRITE));
+
+        let other = credentials(u32::MAX - 1, &[u32::MAX - 1]);
+        assert!(other.allows(&file, &meta, READ));
+        assert!(!other.allows(&file, &meta, EXECUTE));
+    }
+
+    #[test]
+    fn owner_class_applies_even_if_others_have_more() {
+        let dir = TempDir::new("permissions-owner");
+        let (file, meta) = with_mode(&dir, 0o007);
+        let owner = credentials(meta.uid(), &[]);
+        if !owner.is_root() {
+            assert!(!owner.allows(&file, &meta, READ));
+        }
+    }
+
+    #[test]
+    fn root_needs_an_execute_bit() {
+        let dir = TempDir::new("permissions-root");
+        let root = credentials(0, &[0]);
+        let (file, meta) = with_mode(&dir, 0o600);
+        assert!(root.allows(&file, &meta, READ | WRITE));
+        assert!(!root.allows(&file, &meta, EXECUTE));
+        let (file, meta) = with_mode(&dir, 0o001);
+        assert!(root.allows(&file, &meta, EXECUTE));
+    }
+
+    #[test]
+    fn can_execute_needs_search_permission_on_directories() {
+        let dir = TempDir::new("permissions-search");
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        let tool = sub.join("tool");
+        fs::write(&tool, b"").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let meta = fs::metadata(&sub).unwrap();
+        let stranger = credentials(u32::MAX - 1, &[u32::MAX - 1]);
+
+        assert!(stranger.can_execute(&tool));
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o750)).unwrap();
+        assert!(!stranger.can_execute(&tool));
+        assert!(credentials(u32::MAX - 1, &[meta.gid()]).can_execute(&tool));
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(!stranger.can_execute(&sub));
+        assert!(!stranger.can_execute(&sub.join("missing")));
+    }
+
+    #[test]
+    fn parse_group_names_and_ids() {
+        assert_eq!(parse_groups("0,root,42"), Ok(vec![0, 0, 42]));
+        assert!(parse_groups("root,no-such-group").is_err());
+        assert!(parse_groups("").is_err());
+    }
+
+    /// Encode ACL entries as the xattr value.
+    fn acl_value(entries: &[(u16, u16, u32)]) -> Vec<u8> {
+        let mut value = ACL_XATTR_VERSION.to_le_bytes().to_vec();
+        for (tag, perm, id) in entries {
+            value.extend(tag.to_le_bytes());
+            value.extend(perm.to_le_bytes());
+            value.extend(id.to_le_bytes());
+        }
+        value
+    }
+
+    #[test]
+    fn acl_parse() {
+        let value = acl_value(&[(ACL_USER_OBJ, 7, u32::MAX), (ACL_OTHER, 5, u32::MAX)]);
+        let acl = Acl::parse(&value).unwrap();
+        assert_eq!(acl.entries.len(), 2);
+        assert_eq!(acl.perm(ACL_OTHER), Some(5));
+
+        assert_eq!(Acl::parse(&[]), None);
+        assert_eq!(Acl::parse(&value[..value.len() - 1]), None);
+        let mut wrong_version = value.clone();
+        wrong_version[0] = 1;
+        assert_eq!(Acl::parse(&wrong_version), None);
+    }
+
+    #[test]
+    fn acl_access_check() {
+        const NONE: u32 = u32::MAX;
+        // like `setfacl -m u:deploy:rx,g:build:rx,m:r-x` on a 0750 file
+        // owned by 1000:1000
+        let acl = Acl::parse(&acl_value(&[
+            (ACL_USER_OBJ, 7, NONE),
+            (ACL_USER, 7, 2000),
+            (ACL_GROUP_OBJ, 0, NONE),
+            (ACL_GROUP, 5, 3000),
+            (ACL_MASK, 5, NONE),
+            (ACL_OTHER, 0, NONE),
+        ]))
+        .unwrap();
+        let check = |uid, gids: &[u32], perm| acl.allows(&credentials(uid, gids), 1000, 1000, perm);
+
+        assert!(check(1000, &[], READ | WRITE | EXECUTE));
+        // named user, limited by the mask
+        assert!(check(2000, &[], READ | EXECUTE));
+        assert!(!check(2000, &[], WRITE));
+        // named group grants it, even though the owning group does not
+        assert!(check(2500, &[1000, 3000], EXECUTE));
+        // owning group only: matched, so "other" does not apply
+        assert!(!check(2500, &[1000], READ));
+        assert!(!check(2500, &[], READ));
+    }
+}
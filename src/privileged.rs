@@ -0,0 +1,324 @@
+//! Detection of privileged executables.
+//!
+//! A program runs with more privileges than its caller when it has the
+//! setuid or setgid bit, or carries Linux file capabilities in its
+//! `security.capability` extended attribute. This replaces combining
+//! `find -perm` and `getcap` across every PATH directory.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::sys;
+use crate::users;
+
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+
+/// Capability names by bit number, as in `<linux/capability.h>`.
+const CAPABILITY_NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+const VFS_CAP_REVISION_MASK: u32 = 0xFF00_0000;
+const VFS_CAP_REVISION_1: u32 = 0x0100_0000;
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+const VFS_CAP_REVISION_3: u32 = 0x0300_0000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+
+/// Privileges a file grants when executed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Privileges {
+    /// Owner whose identity the file runs with, if it is setuid
+    pub setuid: Option<u32>,
+    /// Group the file runs with, if it is setgid
+    pub setgid: Option<u32>,
+    /// File capabilities in `getcap` notation, e.g. `cap_net_raw+ep`
+    pub capabilities: Option<String>,
+}
+
+impl fmt::Display for Privileges {
+    /// `setuid root, setgid shadow, cap_net_raw+ep`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(uid) = self.setuid {
+            let name = users::by_uid(uid).map_or(uid.to_string(), |user| user.name);
+            parts.push(format!("setuid {}", name));
+        }
+        if let Some(gid) = self.setgid {
+            let name = users::group_name(gid).unwrap_or(gid.to_string());
+            parts.push(format!("setgid {}", name));
+        }
+        if let Some(capabilities) = &self.capabilities {
+            parts.push(capabilities.clone());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Privileges of the executable at `path` (following symlinks), or `None`
+/// if it has none or is not an executable file.
+pub fn check(path: &Path) -> io::Result<Option<Privileges>> {
+    let meta = fs::metadata(path)?;
+    if !meta.is_file() || meta.mode() & 0o111 == 0 {
+        return Ok(None);
+    }
+    let mode = meta.mode();
+    let privileges = Privileges {
+        setuid: (mode & S_ISUID != 0).then_some(meta.uid()),
+        // without group execute, the setgid bit means mandatory locking
+        setgid: (mode & S_ISGID != 0 && mode & 0o010 != 0).then_some(meta.gid()),
+        capabilities: match sys::xattr(path, "security.capability")? {
+            Some(value) => decode_capabilities(&value),
+            None => None,
+        },
+    };
+    Ok((privileges != Privileges::default()).then_some(privileges))
+}
+
+/// Decode a `security.capability` value (`struct vfs_cap_data`) into
+/// `getcap` notation: capabilities with the same flags are joined by
+/// commas and followed by their flags, e.g. `cap_net_admin,cap_net_raw+ep`.
+///
+/// Returns `None` for malformed values and values without capabilities.
+fn decode_capabilities(value: &[u8]) -> Option<String> {
+    let word = |i: usize| -> Option<u32> {
+        let bytes = value.get(i * 4..i * 4 + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+    let magic = word(0)?;
+    let (words, root_id) = match magic & VFS_CAP_REVISION_MASK {
+        VFS_CAP_REVISION_1 => (1, None),
+        VFS_CAP_REVISION_2 => (2, None),
+        // namespaced file capabilities also record the owning root uid
+        VFS_CAP_REVISION_3 => (2, Some(word(5)?)),
+        _ => return None,
+    };
+    let mut permitted = 0u64;
+    let mut inheritable = 0u64;
+    for i in 0..words {
+        permitted |= u64::from(word(1 + i * 2)?) << (32 * i);
+        inheritable |= u64::from(word(2 + i * 2)?) << (32 * i);
+    }
+    let effective = magic & VFS_CAP_FLAGS_EFFECTIVE != 0;
+
+    // group capabilities by their flags, in order of first appearance
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for bit in 0..64 {
+        let p = permitted >> bit & 1 != 0;
+        let i = inheritable >> bit & 1 != 0;
+        if !p && !i {
+            continue;
+        }
+        let flags = format!(
+            "{}{}{}",
+            if effective { "e" } else { "" },
+            if i { "i" } else { "" },
+            if p { "p" } else { "" }
+        );
+        let name = match CAPABILITY_NAMES.get(bit) {
+            Some(name) => format!("cap_{}", name),
+            None => format!("cap_{}", bit),
+        };
+        match groups
+            .iter_mut()
+            .find(|(group_flags, _)| *group_flags == flags)
+        {
+            Some((_, names)) => names.push(name),
+            None => groups.push((flags, vec![name])),
+        }
+    }
+    if groups.is_empty() {
+        return None;
+    }
+
+    let mut text: Vec<String> = groups
+        .into_iter()
+        .map(|(flags, names)| format!("{}+{}", names.join(","), flags))
+        .collect();
+    if let Some(root_id) = root_id.filter(|&id| id != 0) {
+        text.push(format!("[rootid={}]", root_id));
+    }
+    Some(text.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// `vfs_cap_data` with the given header and (permitted, inheritable)
+    /// pairs.
+    fn cap_data(magic: u32, pairs: &[(u32, u32)], root_id: Option<u32>) -> Vec<u8> {
+        let mut data = magic.to_le_bytes().to_vec();
+        for (permitted, inheritable) in pairs {
+            data.extend(permitted.to_le_bytes());
+            data.extend(inheritable.to_le_bytes());
+        }
+        if let Some(root_id) = root_id {
+            data.extend(root_id.to_le_bytes());
+        }
+        data
+    }
+
+    const NET_RAW: u32 = 1 << 13;
+    const NET_ADMIN: u32 = 1 << 12;
+
+    #[test]
+    fn decode_single_capability() {
+        let value = cap_data(VFS_CAP_REVISION_2 | 1, &[(NET_RAW, 0), (0, 0)], None);
+        assert_eq!(
+            decode_capabilities(&value).as_deref(),
+            Some("cap_net_raw+ep")
+        );
+    }
+
+    #[test]
+    fn decode_groups_capabilities_by_flags() {
+        let value = cap_data(
+            VFS_CAP_REVISION_2 | 1,
+            &[(NET_RAW | NET_ADMIN | 1, NET_ADMIN), (0, 0)],
+            None,
+        );
+        assert_eq!(
+            decode_capabilities(&value).as_deref(),
+            Some("cap_chown,cap_net_raw+ep cap_net_admin+eip")
+        );
+    }
+
+    #[test]
+    fn decode_without_effective_flag() {
+        let value = cap_data(VFS_CAP_REVISION_2, &[(NET_RAW, 0), (0, 0)], None);
+        assert_eq!(
+            decode_capabilities(&value).as_deref(),
+            Some("cap_net_raw+p")
+        );
+    }
+
+    #[test]
+    fn decode_high_capabilities() {
+        // cap_bpf is bit 39, in the second word
+        let value = cap_data(VFS_CAP_REVISION_2 | 1, &[(0, 0), (1 << 7, 0)], None);
+        assert_eq!(decode_capabilities(&value).as_deref(), Some("cap_bpf+ep"));
+        let value = cap_data(VFS_CAP_REVISION_2 | 1, &[(0, 0), (1 << 31, 0)], None);
+        assert_eq!(decode_capabilities(&value).as_deref(), Some("cap_63+ep"));
+    }
+
+    #[test]
+    fn decode_revision_1_and_3() {
+        let value = cap_data(VFS_CAP_REVISION_1 | 1, &[(NET_RAW, 0)], None);
+        assert_eq!(
+            decode_capabilities(&value).as_deref(),
+            Some("cap_net_raw+ep")
+        );
+        let value = cap_data(
+            VFS_CAP_REVISION_3 | 1,
+            &[(NET_RAW, 0), (0, 0)],
+            Some(100000),
+        );
+        assert_eq!(
+            decode_capabilities(&value).as_deref(),
+            Some("cap_net_raw+ep [rootid=100000]")
+        );
+    }
+
+    #[test]
+    fn decode_malformed() {
+        assert_eq!(decode_capabilities(&[]), None);
+        assert_eq!(decode_capabilities(&[1, 2, 3]), None);
+        // revision 2 needs two pairs
+        let value = cap_data(VFS_CAP_REVISION_2 | 1, &[(NET_RAW, 0)], None);
+        assert_eq!(decode_capabilities(&value), None);
+        let value = cap_data(0x0400_0000, &[(NET_RAW, 0), (0, 0)], None);
+        assert_eq!(decode_capabilities(&value), None);
+        // no capabilities at all
+        let value = cap_data(VFS_CAP_REVISION_2 | 1, &[(0, 0), (0, 0)], None);
+        assert_eq!(decode_capabilities(&value), None);
+    }
+
+    #[test]
+    fn setuid_and_setgid_bits() {
+        let dir = TempDir::new("privileged");
+        let file = dir.touch("tool");
+        let meta = fs::metadata(&file).unwrap();
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(check(&file).unwrap(), None);
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o4755)).unwrap();
+        let privileges = check(&file).unwrap().unwrap();
+        assert_eq!(privileges.setuid, Some(meta.uid()));
+        assert_eq!(privileges.setgid, None);
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o2755)).unwrap();
+        let privileges = check(&file).unwrap().unwrap();
+        assert_eq!(privileges.setuid, None);
+        assert_eq!(privileges.setgid, Some(meta.gid()));
+
+        // not executable, so it cannot run with anyone's privileges
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o4644)).unwrap();
+        assert_eq!(check(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        let dir = TempDir::new("privileged-errors");
+        let file = dir.touch("tool");
+        // not "not privileged", which would hide files we could not check
+        let err = check(&file.join("sub")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotADirectory);
+    }
+
+    #[test]
+    fn display() {
+        let privileges = Privileges {
+            setuid: Some(0),
+            setgid: None,
+            capabilities: Some("cap_net_raw+ep".to_string()),
+        };
+        assert_eq!(privileges.to_string(), "setuid root, cap_net_raw+ep");
+    }
+}
@@ -0,0 +1,106 @@
+//! Reading the environment of another process from `/proc`.
+//!
+//! When a service or cron job runs the "wrong" tool, the question is what
+//! its PATH resolves to, not ours.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+
+pub struct ProcessEnv {
+    pid: u32,
+    vars: Vec<(OsString, OsString)>,
+}
+
+impl ProcessEnv {
+    /// Read the environment of process `pid` from `/proc/PID/environ`.
+    pub fn read(pid: u32) -> Result<Self, String> {
+        let path = format!("/proc/{}/environ", pid);
+        let environ = fs::read(&path).map_err(|err| describe_error(pid, &path, err))?;
+        Ok(ProcessEnv {
+            pid,
+            vars: parse_environ(&environ),
+        })
+    }
+
+    /// Value of the environment variable `name`, if the process has it.
+    pub fn var(&self, name: &str) -> Option<OsString> {
+        self.vars
+            .iter()
+            .find(|(key, _)| key.as_bytes() == name.as_bytes())
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Current working directory of the process, from `/proc/PID/cwd`.
+    pub fn cwd(&self) -> Result<PathBuf, String> {
+        let path = format!("/proc/{}/cwd", self.pid);
+        fs::read_link(&path).map_err(|err| describe_error(self.pid, &path, err))
+    }
+}
+
+fn describe_error(pid: u32, path: &str, err: io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::NotFound => format!("No process with PID {}", pid),
+        io::ErrorKind::PermissionDenied => format!(
+            "Permission denied reading {} (is process {} owned by another user?)",
+            path, pid
+        ),
+        _ => format!("Failed to read {}: {}", path, err),
+    }
+}
+
+/// Split the NUL-separated `KEY=value` records of `/proc/PID/environ`.
+fn parse_environ(environ: &[u8]) -> Vec<(OsString, OsString)> {
+    environ
+        .split(|&b| b == 0)
+        .filter_map(|record| {
+            let eq = record.iter().position(|&b| b == b'=')?;
+            Some((
+                OsString::from_vec(record[..eq].to_vec()),
+                OsString::from_vec(record[eq + 1..].to_vec()),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_environ_records() {
+        let vars = parse_environ(b"HOME=/root\0PATH=/usr/bin:/bin\0EMPTY=\0EQ=a=b\0");
+        assert_eq!(
+            vars,
+            vec![
+                ("HOME".into(), "/root".into()),
+                ("PATH".into(), "/usr/bin:/bin".into()),
+                ("EMPTY".into(), "".into()),
+                ("EQ".into(), "a=b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_environ_skips_malformed_records() {
+        assert_eq!(
+            parse_environ(b"garbage\0\0X=1"),
+            vec![("X".into(), "1".into())]
+        );
+    }
+
+    #[test]
+    fn read_own_environment() {
+        let env = ProcessEnv::read(std::process::id()).unwrap();
+        assert_eq!(env.var("PATH"), std::env::var_os("PATH"));
+        assert_eq!(env.cwd().unwrap(), std::env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn read_missing_process() {
+        let err = ProcessEnv::read(u32::MAX).err().unwrap();
+        assert_eq!(err, format!("No process with PID {}", u32::MAX));
+    }
+}
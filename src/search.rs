@@ -0,0 +1,570 @@
+//! PATH directory scanning.
+//!
+//! Walks the PATH directories in order and reports every file whose name is
+//! accepted by a `FileNameFilter`. All output modes are built on top of this,
+//! so they agree on what "a match" is and in which order matches appear.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::dir_reader::DirReader;
+use crate::filename_filter::{FileNameFilter, FilterResult, MatchRange};
+use crate::permissions::Credentials;
+
+/// A file in a PATH directory that matched the filename filter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// Index of the directory in the PATH list
+    pub dir_index: usize,
+    /// Normalized directory path (see `normalize_dir`)
+    pub dir: String,
+    pub file_name: String,
+    pub range: MatchRange,
+}
+
+impl Match {
+    /// Full path of the matched file.
+    pub fn path(&self) -> PathBuf {
+        Path::new(&self.dir).join(&self.file_name)
+    }
+}
+
+/// Range of PATH indexes to search, e.g. `0..5`.
+///
+/// Accepts a single index (`3`), an exclusive range (`0..5`), an inclusive
+/// range (`0..=4`) or a range open on either end (`2..`, `..5`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DirRange {
+    start: usize,
+    /// Exclusive end; `None` means up to the end of PATH
+    end: Option<usize>,
+}
+
+impl DirRange {
+    pub fn contains(&self, index: usize) -> bool {
+        index >= self.start && self.end.is_none_or(|end| index < end)
+    }
+}
+
+impl FromStr for DirRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid directory range '{}'", s);
+        let parse_index = |value: &str| value.parse::<usize>().map_err(|_| invalid());
+        // one past an index, which `usize::MAX` does not have
+        let parse_end = |value: &str| parse_index(value)?.checked_add(1).ok_or_else(invalid);
+
+        let (start, end) = match s.split_once("..") {
+            None => (parse_index(s)?, Some(parse_end(s)?)),
+            Some((start, end)) => {
+                let start = if start.is_empty() {
+                    0
+                } else {
+                    parse_index(start)?
+                };
+                let end = if let Some(end) = end.strip_prefix('=') {
+                    Some(parse_end(end)?)
+                } else if end.is_empty() {
+                    None
+                } else {
+                    Some(parse_index(end)?)
+                };
+                (start, end)
+            }
+        };
+
+        if end.is_some_and(|end| end <= start) {
+            return Err(format!("Empty directory range '{}'", s));
+        }
+
+        Ok(DirRange { start, end })
+    }
+}
+
+/// Search `dirs` in order, calling `on_match` for each matching file.
+///
+/// Only directories whose index is in `range` are searched. Directories that
+/// cannot be read are silently skipped; users often have nonexistent
+/// directories in their PATH. So are directories `reader` gives up on.
+pub fn search(
+    reader: &DirReader,
+    dirs: &[PathBuf],
+    range: DirRange,
+    filter: &dyn FileNameFilter,
+    mut on_match: impl FnMut(Match),
+) {
+    for (dir_index, dir) in dirs.iter().enumerate() {
+        if !range.contains(dir_index) {
+            continue;
+        }
+        if let Some(matches) = search_dir(reader, dir_index, dir, filter) {
+            matches.into_iter().for_each(&mut on_match);
+        }
+    }
+}
+
+/// Search a single PATH directory, returning its matches in directory order.
+///
+/// Returns `None` if the directory cannot be read.
+pub fn search_dir(
+    reader: &DirReader,
+    dir_index: usize,
+    dir: &Path,
+    filter: &dyn FileNameFilter,
+) -> Option<Vec<Match>> {
+    let files = reader.list(dir)?;
+    let dir_str = normalize_dir(dir);
+    let mut matches = Vec::new();
+
+    for file in files {
+        let file_name = file.display().to_string();
+
+        if let FilterResult::Matched(range) = filter.filter(&file_name) {
+            matches.push(Match {
+                dir_index,
+                dir: dir_str.clone(),
+                file_name,
+                range,
+            });
+        }
+    }
+
+    Some(matches)
+}
+
+/// Whether the shell would run `path` as a command: after following
+/// symlinks it must be a regular file with at least one execute bit set.
+pub fn is_command(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+/// Mirrors shell command resolution over a stream of matches in PATH order.
+///
+/// With `commands_only`, the first executable entry for a name wins;
+/// non-executable files and dangling symlinks are skipped, just like the
+/// shell skips them. Otherwise (for path lists other than PATH, such as
+/// `LD_LIBRARY_PATH` or `CDPATH`) the first existing entry wins.
+///
+/// With `user`, executability is decided for those credentials instead of
+/// by the execute bits alone.
+pub struct Resolver<'a> {
+    seen: HashSet<String>,
+    commands_only: bool,
+    user: Option<&'a Credentials>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(commands_only: bool, user: Option<&'a Credentials>) -> Self {
+        Resolver {
+            seen: HashSet::new(),
+            commands_only,
+            user,
+        }
+    }
+
+    /// Returns true if `m` is the entry that wins for its name.
+    pub fn is_winner(&mut self, m: &Match) -> bool {
+        if self.seen.contains(&m.file_name) {
+            return false;
+        }
+        let usable = match (self.commands_only, self.user) {
+            (true, Some(user)) => user.can_execute(&m.path()),
+            (true, None) => is_command(&m.path()),
+            (false, _) => fs::metadata(m.path()).is_ok(),
+        };
+        usable && self.seen.insert(m.file_name.clone())
+    }
+}
+
+/// Resolve every matching name the way the shell would (see `Resolver`).
+///
+/// Returns the path of the winning entry for each name.
+pub fn resolve(
+    reader: &DirReader,
+    dirs: &[PathBuf],
+    filter: &dyn FileNameFilter,
+    commands_only: bool,
+    user: Option<&Credentials>,
+) -> BTreeMap<String, PathBuf> {
+    let mut resolver = Resolver::new(commands_only, user);
+    let mut resolved = BTreeMap::new();
+    search(reader, dirs, DirRange::default(), filter, |m| {
+        if resolver.is_winner(&m) {
+            resolved.insert(m.file_name.clone(), m.path());
+        }
+    });
+    resolved
+}
+
+/// Rank each match among the matches sharing its name.
+///
+/// Returns `(rank, total)` for each match, in the same order; rank 1 is the
+/// first copy in PATH order.
+pub fn rank_by_name(matches: &[Match]) -> Vec<(usize, usize)> {
+    let mut totals: HashMap<&str, usize> = HashMap::new();
+    for m in matches {
+        *totals.entry(&m.file_name).or_default() += 1;
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    matches
+        .iter()
+        .map(|m| {
+            let rank = seen.entry(&m.file_name).or_default();
+            *rank += 1;
+            (*rank, totals[m.file_name.as_str()])
+        })
+        .collect()
+}
+
+/// Group matches by file name.
+///
+/// Groups are ordered by the first appearance of each name, and each group
+/// keeps PATH order, so the first entry of a group is the one the shell
+/// would execute.
+pub fn group_by_name(matches: Vec<Match>) -> Vec<Vec<Match>> {
+    let mut groups: Vec<Vec<Match>> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for m in matches {
+        match index.get(&m.file_name) {
+            Some(&i) => groups[i].push(m),
+            None => {
+                index.insert(m.file_name.clone(), groups.len());
+                groups.push(vec![m]);
+            }
+        }
+    }
+
+    groups
+}
+
+/// Normalize a directory path by removing trailing separators.
+///
+/// Converts the path to `String`, stripping any trailing path separators
+/// (`MAIN_SEPARATOR`).
+pub fn normalize_dir(pb: &Path) -> String {
+    let s = pb.display().to_string();
+    s.trim_end_matches(MAIN_SEPARATOR).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ========================================
+    //  normalize_dir tests
+    // ========================================
+
+    mod normalize_dir {
+        use super::*;
+        use std::path::{MAIN_SEPARATOR as SEP, PathBuf};
+
+        #[test]
+        fn no_path_separator_suffix() {
+            const DIR: &str = "/fake/dir";
+            let pb = PathBuf::from(DIR);
+            let normalized = normalize_dir(&pb);
+            assert_eq!(normalized, DIR);
+        }
+
+        #[test]
+        fn remove_path_separator_suffix() {
+            const DIR: &str = "/fake/dir";
+            let pb = PathBuf::from(format!("{DIR}{SEP}"));
+            let normalized = normalize_dir(&pb);
+            assert_eq!(normalized, DIR);
+        }
+
+        #[test]
+        fn remove_multiple_path_separator_suffix() {
+            const DIR: &str = "/fake/dir";
+            let pb = PathBuf::from(format!("{DIR}{SEP}{SEP}{SEP}"));
+            let normalized = normalize_dir(&pb);
+            assert_eq!(normalized, DIR);
+        }
+    }
+
+    // ========================================
+    // DirRange tests
+    // ========================================
+
+    mod dir_range {
+        use super::*;
+
+        fn parse(s: &str) -> DirRange {
+            s.parse().unwrap()
+        }
+
+        #[test]
+        fn default_contains_everything() {
+            let range = DirRange::default();
+            assert!(range.contains(0));
+            assert!(range.contains(usize::MAX));
+        }
+
+        #[test]
+        fn single_index() {
+            let range = parse("3");
+            assert!(!range.contains(2));
+            assert!(range.contains(3));
+            assert!(!range.contains(4));
+        }
+
+        #[test]
+        fn exclusive_range() {
+            let range = parse("0..5");
+            assert!(range.contains(0));
+            assert!(range.contains(4));
+            assert!(!range.contains(5));
+        }
+
+        #[test]
+        fn inclusive_range() {
+            let range = parse("2..=4");
+            assert!(!range.contains(1));
+            assert!(range.contains(4));
+            assert!(!range.contains(5));
+        }
+
+        #[test]
+        fn open_ranges() {
+            assert_eq!(
+                parse("2.."),
+                DirRange {
+                    start: 2,
+                    end: None
+                }
+            );
+            assert_eq!(
+                parse("..5"),
+                DirRange {
+                    start: 0,
+                    end: Some(5)
+                }
+            );
+            assert_eq!(parse(".."), DirRange::default());
+        }
+
+        #[test]
+        fn invalid_ranges() {
+            assert!("".parse::<DirRange>().is_err());
+            assert!("a..b".parse::<DirRange>().is_err());
+            assert!("-1".parse::<DirRange>().is_err());
+            assert!("5..2".parse::<DirRange>().is_err());
+            assert!("3..3".parse::<DirRange>().is_err());
+        }
+
+        #[test]
+        fn ranges_ending_past_usize_max() {
+            let max = usize::MAX.to_string();
+            for range in [max.clone(), format!("0..={}", max)] {
+                assert_eq!(
+                    range.parse::<DirRange>(),
+                    Err(format!("Invalid directory range '{}'", range))
+                );
+            }
+            assert!(parse(&format!("0..{}", max)).contains(usize::MAX - 1));
+        }
+    }
+
+    // ========================================
+    // rank_by_name tests
+    // ========================================
+
+    mod rank_by_name {
+        use super::*;
+
+        fn m(dir_index: usize, file_name: &str) -> Match {
+            Match {
+                dir_index,
+                dir: format!("/dir{dir_index}"),
+                file_name: file_name.to_string(),
+                range: MatchRange::None,
+            }
+        }
+
+        #[test]
+        fn ranks_within_each_name() {
+            let matches = vec![
+                m(0, "python3"),
+                m(1, "ls"),
+                m(2, "python3"),
+                m(5, "python3"),
+            ];
+            assert_eq!(rank_by_name(&matches), vec![(1, 3), (1, 1), (2, 3), (3, 3)]);
+        }
+    }
+
+    // ========================================
+    // search tests
+    // ========================================
+
+    mod search {
+        use super::*;
+        use crate::filename_filter::MatchAllFilter;
+        use crate::test_util::FakeSource;
+        use std::time::Duration;
+
+        #[test]
+        fn slow_directory_is_skipped_in_path_order() {
+            let (source, _release) = FakeSource::new();
+            let reader = DirReader::with_source(source, Some(Duration::from_millis(50)));
+            let dirs = [
+                PathBuf::from("/a"),
+                PathBuf::from("/slow"),
+                PathBuf::from("/b"),
+            ];
+            let mut found = Vec::new();
+            search(
+                &reader,
+                &dirs,
+                DirRange::default(),
+                &MatchAllFilter::default(),
+                |m| found.push((m.dir_index, m.dir)),
+            );
+            assert_eq!(found, [(0, "/a".to_string()), (2, "/b".to_string())]);
+        }
+    }
+
+    // ========================================
+    // Resolver tests
+    // ========================================
+
+    mod resolver {
+        use super::*;
+        use crate::test_util::TempDir;
+
+        fn m(dir: &TempDir, file_name: &str) -> Match {
+            Match {
+                dir_index: 0,
+                dir: normalize_dir(dir.path()),
+                file_name: file_name.to_string(),
+                range: MatchRange::None,
+            }
+        }
+
+        fn executable(dir: &TempDir, name: &str) {
+            let file = dir.touch(name);
+            fs::set_permissions(file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        #[test]
+        fn first_executable_wins() {
+            let a = TempDir::new("resolver-first-a");
+            let b = TempDir::new("resolver-first-b");
+            executable(&a, "tool");
+            executable(&b, "tool");
+
+            let mut resolver = Resolver::new(true, None);
+            assert!(resolver.is_winner(&m(&a, "tool")));
+            assert!(!resolver.is_winner(&m(&b, "tool")));
+        }
+
+        #[test]
+        fn non_executable_is_skipped() {
+            let a = TempDir::new("resolver-non-exec-a");
+            let b = TempDir::new("resolver-non-exec-b");
+            a.touch("tool");
+            executable(&b, "tool");
+
+            let mut resolver = Resolver::new(true, None);
+            assert!(!resolver.is_winner(&m(&a, "tool")));
+            assert!(resolver.is_winner(&m(&b, "tool")));
+        }
+
+        #[test]
+        fn any_existing_file_wins_when_not_commands_only() {
+            let a = TempDir::new("resolver-any-a");
+            let b = TempDir::new("resolver-any-b");
+            std::os::unix::fs::symlink("missing", a.path().join("libfoo.so")).unwrap();
+            b.touch("libfoo.so");
+
+            let mut resolver = Resolver::new(false, None);
+            assert!(!resolver.is_winner(&m(&a, "libfoo.so")));
+            assert!(resolver.is_winner(&m(&b, "libfoo.so")));
+        }
+
+        #[test]
+        fn directory_is_skipped() {
+            let a = TempDir::new("resolver-dir");
+            fs::create_dir(a.path().join("tool")).unwrap();
+
+            let mut resolver = Resolver::new(true, None);
+            assert!(!resolver.is_winner(&m(&a, "tool")));
+        }
+
+        #[test]
+        fn executable_for_user() {
+            let a = TempDir::new("resolver-user-a");
+            let b = TempDir::new("resolver-user-b");
+            let owner_only = a.touch("tool");
+            fs::set_permissions(owner_only, fs::Permissions::from_mode(0o700)).unwrap();
+            executable(&b, "tool");
+
+            let stranger = Credentials {
+                name: "stranger".to_string(),
+                uid: u32::MAX - 1,
+                gids: vec![u32::MAX - 1],
+            };
+            let mut resolver = Resolver::new(true, Some(&stranger));
+            assert!(!resolver.is_winner(&m(&a, "tool")));
+            assert!(resolver.is_winner(&m(&b, "tool")));
+        }
+    }
+
+    // ========================================
+    // group_by_name tests
+    // ========================================
+
+    mod group_by_name {
+        use super::*;
+
+        fn m(dir_index: usize, file_name: &str) -> Match {
+            Match {
+                dir_index,
+                dir: format!("/dir{dir_index}"),
+                file_name: file_name.to_string(),
+                range: MatchRange::None,
+            }
+        }
+
+        #[test]
+        fn empty() {
+            assert!(group_by_name(Vec::new()).is_empty());
+        }
+
+        #[test]
+        fn unique_names_are_single_groups() {
+            let groups = group_by_name(vec![m(0, "ls"), m(1, "cat")]);
+            assert_eq!(groups, vec![vec![m(0, "ls")], vec![m(1, "cat")]]);
+        }
+
+        #[test]
+        fn groups_keep_path_order_and_first_appearance_order() {
+            let groups = group_by_name(vec![
+                m(0, "python3"),
+                m(0, "pip"),
+                m(1, "ls"),
+                m(2, "python3"),
+                m(3, "pip"),
+                m(4, "python3"),
+            ]);
+            assert_eq!(
+                groups,
+                vec![
+                    vec![m(0, "python3"), m(2, "python3"), m(4, "python3")],
+                    vec![m(0, "pip"), m(3, "pip")],
+                    vec![m(1, "ls")],
+                ]
+            );
+        }
+    }
+}
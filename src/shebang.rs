@@ -0,0 +1,412 @@
+//! Checking the `#!` line of scripts.
+//!
+//! A script whose interpreter is gone (a stale virtualenv, a removed
+//! language version) still lists as an executable, but fails with a
+//! confusing "No such file or directory" when run. The kernel only reads the
+//! first `BINPRM_BUF_SIZE` bytes of a script, so that is all that is read
+//! here too.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::dir_reader::DirReader;
+use crate::search;
+
+/// Bytes of a script the Linux kernel reads to find its interpreter
+/// (since Linux 5.1; 128 before).
+const BINPRM_BUF_SIZE: usize = 256;
+
+/// A parsed `#!` line.
+#[derive(Debug, PartialEq)]
+pub struct Shebang {
+    /// Interpreter path, e.g. `/usr/bin/env`
+    pub interpreter: OsString,
+    /// The rest of the line, which the kernel passes as a single argument
+    pub arg: Option<OsString>,
+    /// The line ends with `\r\n`; the `\r` is not part of `interpreter` or
+    /// `arg`, though the kernel keeps it
+    pub crlf: bool,
+    /// The line does not fit in `BINPRM_BUF_SIZE`, so the kernel only sees
+    /// part of it
+    pub too_long: bool,
+}
+
+impl Shebang {
+    /// Read the `#!` line of `path`, or `None` if it does not start with
+    /// one.
+    pub fn read(path: &Path) -> io::Result<Option<Shebang>> {
+        let mut head = Vec::with_capacity(BINPRM_BUF_SIZE);
+        File::open(path)?
+            .take(BINPRM_BUF_SIZE as u64)
+            .read_to_end(&mut head)?;
+        Ok(Self::parse(&head))
+    }
+
+    /// Parse the first `BINPRM_BUF_SIZE` bytes of a file the way the kernel
+    /// does: the interpreter is everything up to the first space or tab,
+    /// and the rest of the line, trimmed, is one argument.
+    fn parse(head: &[u8]) -> Option<Shebang> {
+        let line = head.strip_prefix(b"#!")?;
+        let (line, too_long) = match line.iter().position(|&b| b == b'\n') {
+            Some(end) => (&line[..end], false),
+            None => (line, head.len() == BINPRM_BUF_SIZE),
+        };
+        let crlf = !too_long && line.ends_with(b"\r");
+        let line = trim(line.strip_suffix(b"\r").unwrap_or(line));
+        let (interpreter, arg) = match line.iter().position(|&b| is_blank(b)) {
+            Some(end) => (&line[..end], Some(trim(&line[end..]))),
+            None => (line, None),
+        };
+        Some(Shebang {
+            interpreter: OsStr::from_bytes(interpreter).to_os_string(),
+            arg: arg
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| OsStr::from_bytes(arg).to_os_string()),
+            crlf,
+            too_long,
+        })
+    }
+
+    /// Whether the interpreter is `env`, which looks up the actual
+    /// interpreter in PATH.
+    pub fn is_env(&self) -> bool {
+        Path::new(&self.interpreter).file_name() == Some(OsStr::new("env"))
+    }
+
+    /// Command `env` runs for this line: the whole argument, or with
+    /// `env -S`, the first word of it that is not an option or variable
+    /// assignment.
+    pub fn env_command(&self) -> Option<&OsStr> {
+        let arg = self.arg.as_deref()?.as_bytes();
+        let command = match arg.strip_prefix(b"-S") {
+            Some(words) => words.split(|&b| is_blank(b)).find(|word| {
+                !word.is_empty() && !word.starts_with(b"-") && !word.contains(&b'=')
+            })?,
+            None => arg,
+        };
+        Some(OsStr::from_bytes(command))
+    }
+
+    /// Program that runs the script: the interpreter, or with `env`, the
+    /// command found in `dirs`. A command `env` cannot find is returned as
+    /// it is named. `None` if the line names nothing to run.
+    pub fn program(&self, dirs: &[PathBuf], reader: &DirReader) -> Option<PathBuf> {
+        if self.interpreter.is_empty() {
+            return None;
+        }
+        if !self.is_env() {
+            return Some(PathBuf::from(&self.interpreter));
+        }
+        let command = self.env_command()?;
+        Some(find_command(command, dirs, reader).unwrap_or_else(|| PathBuf::from(command)))
+    }
+}
+
+/// Path `env` runs for `command`, searching `dirs` for names without a
+/// slash like execvp does. Directories `reader` gave up on are skipped.
+fn find_command(command: &OsStr, dirs: &[PathBuf], reader: &DirReader) -> Option<PathBuf> {
+    if command.as_bytes().contains(&b'/') {
+        return Some(PathBuf::from(command));
+    }
+    dirs.iter()
+        .filter(|dir| reader.answers(dir))
+        .map(|dir| dir.join(command))
+        .find(|path| search::is_command(path))
+}
+
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Strip leading and trailing spaces and tabs.
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|&b| !is_blank(b))
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|&b| !is_blank(b))
+        .map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// The `#!` line ends with `\r\n`, so the kernel looks for an
+    /// interpreter (or argument) ending in `\r`
+    Crlf,
+    /// The `#!` line is longer than the kernel reads
+    TooLong,
+    /// Nothing follows `#!`, or `env` is given no command
+    NoInterpreter,
+    MissingInterpreter(PathBuf),
+    /// The interpreter is not an executable file
+    NotExecutable(PathBuf),
+    /// `env` cannot find the command in PATH
+    NotInPath(OsString),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Crlf => write!(f, "#! line ends with CRLF"),
+            Problem::TooLong => write!(
+                f,
+                "#! line is longer than the {} bytes Linux reads",
+                BINPRM_BUF_SIZE
+            ),
+            Problem::NoInterpreter => write!(f, "#! line names no interpreter"),
+            Problem::MissingInterpreter(path) => {
+                write!(f, "interpreter {} does not exist", path.display())
+            }
+            Problem::NotExecutable(path) => {
+                write!(f, "interpreter {} is not executable", path.display())
+            }
+            Problem::NotInPath(command) => {
+                write!(f, "env cannot find '{}' in PATH", command.display())
+            }
+        }
+    }
+}
+
+/// Problems with the `#!` line of the executable at `path`. `env` forms are
+/// resolved against `dirs`, the PATH the script runs with, skipping
+/// directories `reader` gave up on.
+///
+/// Files that are not executable or not scripts have no problems.
+pub fn check(path: &Path, dirs: &[PathBuf], reader: &DirReader) -> Vec<Problem> {
+    if !search::is_command(path) {
+        return Vec::new();
+    }
+    let Ok(Some(shebang)) = Shebang::read(path) else {
+        return Vec::new();
+    };
+
+    let mut problems = Vec::new();
+    if shebang.crlf {
+        problems.push(Problem::Crlf);
+    }
+    if shebang.too_long {
+        problems.push(Problem::TooLong);
+        // without a space, the interpreter itself was cut off
+        if shebang.arg.is_none() {
+            return problems;
+        }
+    }
+    if shebang.interpreter.is_empty() {
+        problems.push(Problem::NoInterpreter);
+        return problems;
+    }
+    if let Some(problem) = check_interpreter(Path::new(&shebang.interpreter)) {
+        problems.push(problem);
+        return problems;
+    }
+
+    if shebang.is_env() {
+        match shebang.env_command() {
+            None => problems.push(Problem::NoInterpreter),
+            Some(command) => match find_command(command, dirs, reader) {
+                Some(path) => problems.extend(check_interpreter(&path)),
+                None => problems.push(Problem::NotInPath(command.to_os_string())),
+            },
+        }
+    }
+    problems
+}
+
+fn check_interpreter(path: &Path) -> Option<Problem> {
+    if fs::metadata(path).is_err() {
+        Some(Problem::MissingInterpreter(path.to_path_buf()))
+    } else if !search::is_command(path) {
+        Some(Problem::NotExecutable(path.to_path_buf()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeSource, TempDir};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    fn shebang(interpreter: &str, arg: Option<&str>) -> Shebang {
+        Shebang {
+            interpreter: interpreter.into(),
+            arg: arg.map(OsString::from),
+            crlf: false,
+            too_long: false,
+        }
+    }
+
+    #[test]
+    fn parse_interpreter_and_argument() {
+        assert_eq!(
+            Shebang::parse(b"#!/bin/sh\necho hi\n"),
+            Some(shebang("/bin/sh", None))
+        );
+        assert_eq!(
+            Shebang::parse(b"#! /usr/bin/env  python3 -u \t\nprint()\n"),
+            Some(shebang("/usr/bin/env", Some("python3 -u")))
+        );
+        assert_eq!(Shebang::parse(b"#!"), Some(shebang("", None)));
+        assert_eq!(Shebang::parse(b"\x7fELF\x02\x01"), None);
+        assert_eq!(Shebang::parse(b""), None);
+    }
+
+    #[test]
+    fn parse_crlf_and_long_lines() {
+        let parsed = Shebang::parse(b"#!/bin/bash\r\necho\r\n").unwrap();
+        assert_eq!(parsed.interpreter, "/bin/bash");
+        assert!(parsed.crlf);
+
+        let mut head = b"#!/usr/bin/env python3 ".to_vec();
+        head.resize(BINPRM_BUF_SIZE, b'x');
+        let parsed = Shebang::parse(&head).unwrap();
+        assert!(parsed.too_long);
+        assert!(!parsed.crlf);
+        assert_eq!(parsed.interpreter, "/usr/bin/env");
+    }
+
+    #[test]
+    fn env_command() {
+        let env = |arg| shebang("/usr/bin/env", Some(arg));
+        assert!(env("python3").is_env());
+        assert!(!shebang("/bin/sh", None).is_env());
+        assert_eq!(env("python3").env_command(), Some(OsStr::new("python3")));
+        // without -S, env gets the whole argument as the command name
+        assert_eq!(
+            env("node --harmony").env_command(),
+            Some(OsStr::new("node --harmony"))
+        );
+        assert_eq!(
+            env("-S -i LANG=C node --harmony").env_command(),
+            Some(OsStr::new("node"))
+        );
+        assert_eq!(env("-S").env_command(), None);
+        assert_eq!(shebang("/usr/bin/env", None).env_command(), None);
+    }
+
+    #[test]
+    fn program_resolves_env() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("shebang-program");
+        let python = script(&dir, "python2.7", "");
+        let dirs = [dir.path().to_path_buf()];
+        assert_eq!(
+            shebang("/usr/bin/python3", Some("-u")).program(&dirs, &reader),
+            Some(PathBuf::from("/usr/bin/python3"))
+        );
+        let env = |arg| shebang("/usr/bin/env", Some(arg));
+        assert_eq!(env("python2.7").program(&dirs, &reader), Some(python));
+        assert_eq!(
+            env("python2.7").program(&[], &reader),
+            Some(PathBuf::from("python2.7"))
+        );
+        assert_eq!(
+            env("/opt/bin/node").program(&[], &reader),
+            Some(PathBuf::from("/opt/bin/node"))
+        );
+        assert_eq!(env("-S").program(&dirs, &reader), None);
+        assert_eq!(shebang("", None).program(&dirs, &reader), None);
+    }
+
+    #[test]
+    fn find_command_skips_directories_given_up_on() {
+        let dir = TempDir::new("shebang-given-up");
+        let tool = script(&dir, "tool", "");
+        let (source, _release) = FakeSource::new();
+        let reader = DirReader::with_source(source, Some(Duration::from_millis(50)));
+        let dirs = [PathBuf::from("/slow"), dir.path().to_path_buf()];
+        assert_eq!(find_command(OsStr::new("tool"), &dirs, &reader), Some(tool));
+        assert!(!reader.answers(Path::new("/slow")));
+    }
+
+    fn script(dir: &TempDir, name: &str, contents: &str) -> PathBuf {
+        let file = dir.path().join(name);
+        fs::write(&file, contents).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        file
+    }
+
+    #[test]
+    fn interpreter_must_exist_and_be_executable() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("shebang-interpreter");
+        let interpreter = script(&dir, "python3", "");
+        let data = dir.touch("data");
+        let ok = script(&dir, "ok", &format!("#!{}\n", interpreter.display()));
+        let gone = script(
+            &dir,
+            "gone",
+            &format!("#!{}/venv/python3\n", dir.path().display()),
+        );
+        let data_script = script(&dir, "data-script", &format!("#!{} -x\n", data.display()));
+
+        assert_eq!(check(&ok, &[], &reader), []);
+        assert_eq!(
+            check(&gone, &[], &reader),
+            [Problem::MissingInterpreter(dir.path().join("venv/python3"))]
+        );
+        assert_eq!(
+            check(&data_script, &[], &reader),
+            [Problem::NotExecutable(data)]
+        );
+    }
+
+    #[test]
+    fn env_resolves_against_path() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("shebang-env");
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        script(&dir, "bin/tool", "");
+        let env_path = Path::new("/usr/bin/env");
+        if !search::is_command(env_path) {
+            return;
+        }
+
+        let found = script(&dir, "found", "#!/usr/bin/env tool\n");
+        let missing = script(&dir, "missing", "#!/usr/bin/env no-such-tool\n");
+        assert_eq!(check(&found, std::slice::from_ref(&bin), &reader), []);
+        assert_eq!(
+            check(&found, &[], &reader),
+            [Problem::NotInPath("tool".into())]
+        );
+        assert_eq!(
+            check(&missing, &[bin], &reader),
+            [Problem::NotInPath("no-such-tool".into())]
+        );
+    }
+
+    #[test]
+    fn crlf_is_reported_with_other_problems() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("shebang-crlf");
+        let file = script(&dir, "tool", "#!/nonexistent/sh\r\necho\r\n");
+        assert_eq!(
+            check(&file, &[], &reader),
+            [
+                Problem::Crlf,
+                Problem::MissingInterpreter("/nonexistent/sh".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn non_scripts_and_non_executables_are_skipped() {
+        let reader = DirReader::new(None);
+        let dir = TempDir::new("shebang-skip");
+        let binary = script(&dir, "binary", "\x7fELF");
+        assert!(check(&binary, &[], &reader).is_empty());
+        let file = dir.path().join("plain");
+        fs::write(&file, "#!/nonexistent/sh\n").unwrap();
+        assert!(check(&file, &[], &reader).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Thin wrappers around libc calls that std doesn't expose.
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ffi::{CString, c_char, c_void};
+use std::ffi::{c_int, c_uint};
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+const SIGKILL: c_int = 9;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ERANGE: c_int = 34;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ENODATA: c_int = 61;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ENOTSUP: c_int = 95;
+
+unsafe extern "C" {
+    fn kill(pid: c_int, sig: c_int) -> c_int;
+    fn geteuid() -> c_uint;
+    fn getegid() -> c_uint;
+    fn getgroups(size: c_int, list: *mut c_uint) -> c_int;
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe extern "C" {
+    fn getxattr(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize)
+    -> isize;
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+mod winsize {
+    use std::ffi::{c_int, c_ulong, c_ushort};
+
+    #[repr(C)]
+    struct WinSize {
+        ws_row: c_ushort,
+        ws_col: c_ushort,
+        ws_xpixel: c_ushort,
+        ws_ypixel: c_ushort,
+    }
+
+    #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    ))]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    /// `_IOR('t', 104, struct winsize)`, which Linux kept on the
+    /// architectures whose ioctl numbers follow BSD
+    #[cfg(not(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    )))]
+    const TIOCGWINSZ: c_ulong = 0x40087468;
+
+    unsafe extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    /// Width in columns of the terminal attached to stdout, if any.
+    pub fn terminal_width() -> Option<usize> {
+        let mut size = WinSize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCGWINSZ writes a `struct winsize` to the pointer, which
+        // matches the layout of `WinSize`.
+        let ret = unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut WinSize) };
+        if ret != 0 || size.ws_col == 0 {
+            return None;
+        }
+        Some(size.ws_col as usize)
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+pub use winsize::terminal_width;
+
+/// Elsewhere `TIOCGWINSZ` is unknown, so only `COLUMNS` sets the width.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+pub fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Forcibly kill every process in the process group led by `pgid`.
+pub fn kill_process_group(pgid: u32) {
+    // SAFETY: kill() has no memory safety requirements; a negative pid
+    // addresses the process group.
+    unsafe {
+        kill(-(pgid as c_int), SIGKILL);
+    }
+}
+
+/// Effective user ID of this process.
+pub fn effective_uid() -> u32 {
+    // SAFETY: geteuid() cannot fail and has no memory safety requirements.
+    unsafe { geteuid() }
+}
+
+/// Effective group ID of this process.
+pub fn effective_gid() -> u32 {
+    // SAFETY: getegid() cannot fail and has no memory safety requirements.
+    unsafe { getegid() }
+}
+
+/// Supplementary group IDs of this process.
+pub fn supplementary_groups() -> Vec<u32> {
+    // SAFETY: with a size of 0, getgroups() only returns the number of
+    // groups and does not touch the list.
+    let count = unsafe { getgroups(0, std::ptr::null_mut()) };
+    if count <= 0 {
+        return Vec::new();
+    }
+    let mut groups = vec![0; count as usize];
+    // SAFETY: `groups` has room for `count` entries.
+    let count = unsafe { getgroups(count, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups
+}
+
+/// Value of the extended attribute `name` of `path` (following symlinks),
+/// or `None` if the file does not have it or the filesystem does not
+/// support extended attributes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn xattr(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    let mut value = vec![0u8; 256];
+    loop {
+        // SAFETY: both strings are NUL-terminated and `value` has room for
+        // `value.len()` bytes.
+        let len = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len >= 0 {
+            value.truncate(len as usize);
+            return Ok(Some(value));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // the value does not fit, so retry with a larger buffer
+            Some(ERANGE) => value.resize(value.len() * 2, 0),
+            Some(ENODATA | ENOTSUP) => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+}
+
+/// Extended attributes are only read on Linux.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn xattr(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
@@ -0,0 +1,241 @@
+//! Helpers shared by unit tests.
+
+use crate::dir_reader::DirSource;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Temporary directory that is removed when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a fresh directory under the system temp dir. `name` only needs
+    /// to be unique within the test binary.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("pathsearch-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("failed to create temp dir");
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Create an empty file in the directory and return its path.
+    pub fn touch(&self, name: &str) -> PathBuf {
+        let file = self.path.join(name);
+        fs::write(&file, b"").expect("failed to create temp file");
+        file
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Fake filesystem where directories named `slow*` hang until the sender
+/// returned by `new` is dropped, directories named `missing*` do not
+/// exist, and any other directory `DIR` holds one file, `DIR-tool`.
+pub struct FakeSource {
+    /// How many times a directory was listed
+    pub calls: AtomicUsize,
+    release: Mutex<Receiver<()>>,
+}
+
+impl FakeSource {
+    pub fn new() -> (Arc<FakeSource>, Sender<()>) {
+        let (sender, receiver) = mpsc::channel();
+        let source = FakeSource {
+            calls: AtomicUsize::new(0),
+            release: Mutex::new(receiver),
+        };
+        (Arc::new(source), sender)
+    }
+}
+
+impl DirSource for FakeSource {
+    fn list(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let name = dir.file_name().unwrap().to_string_lossy();
+        if name.starts_with("slow") {
+            // nothing is ever sent, so this returns once the sender is
+            // dropped
+            let _ = self.release.lock().unwrap().recv();
+        }
+        if name.starts_with("missing") {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(vec![OsString::from(format!("{}-tool", name))])
+    }
+}
+
+/// Minimal 64-bit little-endian ELF file, for tests that inspect dynamic
+/// linking information.
+pub struct ElfSpec<'a> {
+    /// `e_machine`; 62 is x86-64
+    pub machine: u16,
+    pub interpreter: Option<&'a str>,
+    pub needed: &'a [&'a str],
+    pub rpath: Option<&'a str>,
+    pub runpath: Option<&'a str>,
+    /// Undefined and defined dynamic symbols
+    pub imports: &'a [&'a str],
+    pub exports: &'a [&'a str],
+    /// Whether to include a dynamic section at all
+    pub dynamic: bool,
+}
+
+impl Default for ElfSpec<'_> {
+    fn default() -> Self {
+        ElfSpec {
+            machine: 62,
+            interpreter: None,
+            needed: &[],
+            rpath: None,
+            runpath: None,
+            imports: &[],
+            exports: &[],
+            dynamic: true,
+        }
+    }
+}
+
+impl ElfSpec<'_> {
+    /// Contents of the ELF file: a header, program headers, everything
+    /// else in a single loadable segment at address `BASE`, and section
+    /// headers for the dynamic symbol table.
+    pub fn build(&self) -> Vec<u8> {
+        const BASE: u64 = 0x40_0000;
+        const PHDR_SIZE: usize = 56;
+
+        // data after the program headers: interpreter, strings, dynamic
+        let phnum = 1 + usize::from(self.interpreter.is_some()) + usize::from(self.dynamic);
+        let data_start = 64 + phnum * PHDR_SIZE;
+        let mut data = Vec::new();
+
+        let interp = self.interpreter.map(|interpreter| {
+            let offset = data_start + data.len();
+            data.extend(interpreter.as_bytes());
+            data.push(0);
+            (offset, interpreter.len() + 1)
+        });
+
+        let strtab = data_start + data.len();
+        let mut strings = vec![0u8];
+        let mut add_string = |s: &str| {
+            let offset = strings.len() as u64;
+            strings.extend(s.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let mut entries: Vec<(u64, u64)> = Vec::new();
+        for name in self.needed {
+            entries.push((1, add_string(name)));
+        }
+        if let Some(rpath) = self.rpath {
+            entries.push((15, add_string(rpath)));
+        }
+        if let Some(runpath) = self.runpath {
+            entries.push((29, add_string(runpath)));
+        }
+        let mut symbols: Vec<(u64, bool)> = Vec::new();
+        for name in self.imports {
+            symbols.push((add_string(name), true));
+        }
+        for name in self.exports {
+            symbols.push((add_string(name), false));
+        }
+        entries.push((5, BASE + strtab as u64));
+        entries.push((10, strings.len() as u64));
+        entries.push((0, 0));
+        data.extend(&strings);
+        while !(data_start + data.len()).is_multiple_of(8) {
+            data.push(0);
+        }
+
+        let dynamic_offset = data_start + data.len();
+        for (tag, value) in &entries {
+            data.extend(tag.to_le_bytes());
+            data.extend(value.to_le_bytes());
+        }
+        let dynamic_size = entries.len() * 16;
+
+        // a null symbol, then imports (undefined) and exports (defined in
+        // section 1)
+        let dynsym = data_start + data.len();
+        data.extend([0u8; 24]);
+        for (name, undefined) in &symbols {
+            data.extend((*name as u32).to_le_bytes());
+            data.push(0x12); // st_info: global function
+            data.push(0); // st_other
+            data.extend(if *undefined { 0u16 } else { 1u16 }.to_le_bytes());
+            data.extend([0u8; 16]); // st_value, st_size
+        }
+        let total = (data_start + data.len()) as u64;
+        let shnum: u16 = if self.dynamic { 3 } else { 0 };
+
+        let mut elf = Vec::new();
+        elf.extend(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+        elf.extend(2u16.to_le_bytes()); // e_type: executable
+        elf.extend(self.machine.to_le_bytes());
+        elf.extend(1u32.to_le_bytes()); // e_version
+        elf.extend(BASE.to_le_bytes()); // e_entry
+        elf.extend(64u64.to_le_bytes()); // e_phoff
+        elf.extend(total.to_le_bytes()); // e_shoff
+        elf.extend(0u32.to_le_bytes()); // e_flags
+        elf.extend(64u16.to_le_bytes()); // e_ehsize
+        elf.extend((PHDR_SIZE as u16).to_le_bytes());
+        elf.extend((phnum as u16).to_le_bytes());
+        elf.extend(64u16.to_le_bytes()); // e_shentsize
+        elf.extend(shnum.to_le_bytes());
+        elf.extend(0u16.to_le_bytes()); // e_shstrndx
+
+        let mut phdr = |kind: u32, offset: u64, size: u64| {
+            elf.extend(kind.to_le_bytes());
+            elf.extend(4u32.to_le_bytes()); // p_flags
+            elf.extend(offset.to_le_bytes());
+            elf.extend((BASE + offset).to_le_bytes()); // p_vaddr
+            elf.extend((BASE + offset).to_le_bytes()); // p_paddr
+            elf.extend(size.to_le_bytes()); // p_filesz
+            elf.extend(size.to_le_bytes()); // p_memsz
+            elf.extend(8u64.to_le_bytes()); // p_align
+        };
+        phdr(1, 0, total);
+        if let Some((offset, size)) = interp {
+            phdr(3, offset as u64, size as u64);
+        }
+        if self.dynamic {
+            phdr(2, dynamic_offset as u64, dynamic_size as u64);
+        }
+        elf.extend(data);
+
+        if self.dynamic {
+            let mut shdr = |kind: u32, offset: usize, size: usize, link: u32, entsize: u64| {
+                elf.extend(0u32.to_le_bytes()); // sh_name
+                elf.extend(kind.to_le_bytes());
+                elf.extend(0u64.to_le_bytes()); // sh_flags
+                elf.extend((BASE + offset as u64).to_le_bytes()); // sh_addr
+                elf.extend((offset as u64).to_le_bytes());
+                elf.extend((size as u64).to_le_bytes());
+                elf.extend(link.to_le_bytes());
+                elf.extend(0u32.to_le_bytes()); // sh_info
+                elf.extend(8u64.to_le_bytes()); // sh_addralign
+                elf.extend(entsize.to_le_bytes());
+            };
+            shdr(0, 0, 0, 0, 0);
+            shdr(3, strtab, strings.len(), 0, 0); // .dynstr
+            shdr(11, dynsym, (symbols.len() + 1) * 24, 1, 24); // .dynsym
+        }
+        elf
+    }
+}
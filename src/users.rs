@@ -0,0 +1,180 @@
+//! User and group lookups from `/etc/passwd` and `/etc/group`.
+//!
+//! Only local accounts are found; users from NSS sources such as LDAP are
+//! not.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    /// Primary group
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// Look up a user by name.
+pub fn by_name(name: &str) -> Option<User> {
+    passwd().iter().find(|user| user.name == name).cloned()
+}
+
+/// Look up a user by ID.
+pub fn by_uid(uid: u32) -> Option<User> {
+    passwd().iter().find(|user| user.uid == uid).cloned()
+}
+
+/// Name of the group `gid`.
+pub fn group_name(gid: u32) -> Option<String> {
+    groups()
+        .iter()
+        .find(|group| group.gid == gid)
+        .map(|group| group.name.clone())
+}
+
+/// ID of the group called `name`.
+pub fn group_id(name: &str) -> Option<u32> {
+    groups()
+        .iter()
+        .find(|group| group.name == name)
+        .map(|group| group.gid)
+}
+
+/// Groups of `user`: their primary group followed by every group that
+/// lists them as a member.
+pub fn group_ids(user: &User) -> Vec<u32> {
+    member_group_ids(user, groups())
+}
+
+fn member_group_ids(user: &User, groups: &[Group]) -> Vec<u32> {
+    let mut gids = vec![user.gid];
+    for group in groups {
+        if group.members.contains(&user.name) && !gids.contains(&group.gid) {
+            gids.push(group.gid);
+        }
+    }
+    gids
+}
+
+#[derive(Debug, PartialEq)]
+struct Group {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+/// Entries of `/etc/group`, read once per run.
+fn groups() -> &'static [Group] {
+    static GROUPS: OnceLock<Vec<Group>> = OnceLock::new();
+    GROUPS.get_or_init(|| {
+        fs::read_to_string("/etc/group")
+            .map(|group| parse_group(&group))
+            .unwrap_or_default()
+    })
+}
+
+/// Entries of `/etc/passwd`, read once per run.
+fn passwd() -> &'static [User] {
+    static PASSWD: OnceLock<Vec<User>> = OnceLock::new();
+    PASSWD.get_or_init(|| {
+        fs::read_to_string("/etc/passwd")
+            .map(|passwd| parse_passwd(&passwd))
+            .unwrap_or_default()
+    })
+}
+
+/// Parse passwd-format lines: `name:password:uid:gid:gecos:home:shell`.
+fn parse_passwd(passwd: &str) -> Vec<User> {
+    passwd
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 7 {
+                return None;
+            }
+            Some(User {
+                name: fields[0].to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+                home: PathBuf::from(fields[5]),
+            })
+        })
+        .collect()
+}
+
+/// Parse group-format lines: `name:password:gid:member,member`.
+fn parse_group(group: &str) -> Vec<Group> {
+    group
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some(Group {
+                name: fields[0].to_string(),
+                gid: fields[2].parse().ok()?,
+                members: fields[3]
+                    .split(',')
+                    .filter(|member| !member.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\n\
+                          # comment\n\
+                          alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh\n\
+                          broken:x:notanumber:1:::\n";
+
+    #[test]
+    fn parse_passwd_entries() {
+        let users = parse_passwd(PASSWD);
+        assert_eq!(users.len(), 2);
+        assert_eq!(
+            users[1],
+            User {
+                name: "alice".to_string(),
+                uid: 1000,
+                gid: 1000,
+                home: PathBuf::from("/home/alice"),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_group_entries() {
+        let groups = parse_group("root:x:0:\nwheel:x:10:alice,bob\nbad:x:\n");
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].members.is_empty());
+        assert_eq!(
+            groups[1],
+            Group {
+                name: "wheel".to_string(),
+                gid: 10,
+                members: vec!["alice".to_string(), "bob".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn member_groups_start_with_primary_group() {
+        let alice = &parse_passwd(PASSWD)[1];
+        let groups = parse_group("alice:x:1000:\nwheel:x:10:alice,bob\ndocker:x:20:bob\n");
+        assert_eq!(member_group_ids(alice, &groups), [1000, 10]);
+    }
+
+    #[test]
+    fn root_exists() {
+        assert_eq!(by_uid(0).map(|user| user.name), Some("root".to_string()));
+        assert_eq!(by_name("root").map(|user| user.uid), Some(0));
+    }
+}